@@ -1,3 +1,34 @@
 fn main() {
-    tauri_build::build()
+    // Once the app has an ACL manifest every app command needs a permission
+    // granted in `capabilities/`, so each one registered with
+    // `generate_handler!` is listed here. The permissions are in
+    // `permissions/`.
+    tauri_build::try_build(tauri_build::Attributes::new().app_manifest(
+        tauri_build::AppManifest::new().commands(&[
+            "get_server_state",
+            "get_server_url",
+            "get_launcher",
+            "get_server_logs",
+            "open_server_log",
+            "get_orphan_server",
+            "reap_orphan_server",
+            "retry_startup",
+            "run_preflight",
+            "export_diagnostics",
+            "get_settings",
+            "update_settings",
+            "get_client_url",
+            "get_vault_status",
+            "unlock_vault",
+            "create_vault",
+            "lock_vault",
+            "list_secrets",
+            "set_secret",
+            "delete_secret",
+            "start_server",
+            "stop_server",
+            "restart_server",
+        ]),
+    ))
+    .expect("failed to run tauri-build");
 }
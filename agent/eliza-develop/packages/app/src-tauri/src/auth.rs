@@ -0,0 +1,63 @@
+use std::fs;
+use std::io;
+use std::path::Path;
+use std::sync::Mutex;
+
+use crate::paths;
+
+/// Name of the file in the app data dir that holds this install's token.
+pub const FILE_NAME: &str = "server-auth-token";
+
+/// Bytes of randomness in a generated token, hex-encoded on disk.
+const TOKEN_BYTES: usize = 32;
+
+static TOKEN: Mutex<Option<String>> = Mutex::new(None);
+
+/// Loads this install's server token from `path`, generating and saving one
+/// on first launch. Without it the spawned server's API stays open to every
+/// local process, so a failure is reported but does not stop the app.
+pub fn init(path: &Path) {
+    match load_or_create(path) {
+        Ok(token) => *TOKEN.lock().expect("TOKEN mutex should not be poisoned") = Some(token),
+        Err(err) => eprintln!(
+            "Failed to set up the server auth token at {}; the server API will not require one: {}",
+            path.display(),
+            err
+        ),
+    }
+}
+
+/// The token the app gives the server it spawns, if one could be set up.
+pub fn token() -> Option<String> {
+    TOKEN
+        .lock()
+        .expect("TOKEN mutex should not be poisoned")
+        .clone()
+}
+
+fn load_or_create(path: &Path) -> io::Result<String> {
+    match fs::read_to_string(path) {
+        Ok(token) if is_token(token.trim()) => return Ok(token.trim().to_string()),
+        Ok(_) => eprintln!(
+            "Replacing malformed server auth token in {}",
+            path.display()
+        ),
+        Err(err) if err.kind() == io::ErrorKind::NotFound => {}
+        Err(err) => return Err(err),
+    }
+    let token = generate()?;
+    paths::write_private(path, token.as_bytes())?;
+    println!("Generated server auth token in {}", path.display());
+    Ok(token)
+}
+
+/// A new random token, hex-encoded.
+pub fn generate() -> io::Result<String> {
+    let mut bytes = [0u8; TOKEN_BYTES];
+    getrandom::fill(&mut bytes).map_err(|err| io::Error::other(err.to_string()))?;
+    Ok(bytes.iter().map(|byte| format!("{:02x}", byte)).collect())
+}
+
+fn is_token(token: &str) -> bool {
+    token.len() == TOKEN_BYTES * 2 && token.chars().all(|c| c.is_ascii_hexdigit())
+}
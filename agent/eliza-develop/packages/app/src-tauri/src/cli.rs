@@ -0,0 +1,381 @@
+use serde_json::Value;
+use std::io::{self, BufRead, BufReader, IsTerminal, Write};
+use zeroize::Zeroizing;
+
+use crate::instance::{self, Request};
+
+/// A subcommand run against the instance that is already running.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Command {
+    Status,
+    Logs {
+        follow: bool,
+    },
+    Stop,
+    Open {
+        agent_id: String,
+    },
+    CreateVault,
+    UnlockVault,
+    LockVault,
+    ListSecrets,
+    /// The value is read from stdin rather than taken as an argument, so it
+    /// stays out of the shell history and the process list.
+    SetSecret {
+        name: String,
+        inject: bool,
+    },
+    DeleteSecret {
+        name: String,
+    },
+}
+
+const USAGE: &str = "usage: app status | app logs [--follow] | app stop | app open <agentId>
+       app vault create|unlock|lock
+       app secrets [list] | app secrets set <NAME> [--no-inject] | app secrets delete <NAME>";
+
+/// Parses the command-line arguments after the program name. Returns `None`
+/// when they do not start with a subcommand, so the app launches normally.
+pub fn parse(mut args: impl Iterator<Item = String>) -> Option<Result<Command, String>> {
+    let command = match args.next()?.as_str() {
+        "status" => Ok(Command::Status),
+        "logs" => {
+            let mut follow = false;
+            for arg in args.by_ref() {
+                match arg.as_str() {
+                    "--follow" | "-f" => follow = true,
+                    other => return Some(Err(format!("unknown option {:?}\n{}", other, USAGE))),
+                }
+            }
+            Ok(Command::Logs { follow })
+        }
+        "stop" => Ok(Command::Stop),
+        "open" => match args.next() {
+            Some(agent_id) if !agent_id.is_empty() && !agent_id.starts_with('-') => {
+                Ok(Command::Open { agent_id })
+            }
+            _ => Err(USAGE.to_string()),
+        },
+        "vault" => match args.next().as_deref() {
+            Some("create") => Ok(Command::CreateVault),
+            Some("unlock") => Ok(Command::UnlockVault),
+            Some("lock") => Ok(Command::LockVault),
+            _ => Err(USAGE.to_string()),
+        },
+        "secrets" => match args.next().as_deref() {
+            None | Some("list") => Ok(Command::ListSecrets),
+            Some("set") => match args.next() {
+                Some(name) if !name.is_empty() && !name.starts_with('-') => {
+                    let mut inject = true;
+                    for arg in args.by_ref() {
+                        match arg.as_str() {
+                            "--no-inject" => inject = false,
+                            other => {
+                                return Some(Err(format!("unknown option {:?}\n{}", other, USAGE)))
+                            }
+                        }
+                    }
+                    Ok(Command::SetSecret { name, inject })
+                }
+                _ => Err(USAGE.to_string()),
+            },
+            Some("delete") => match args.next() {
+                Some(name) if !name.is_empty() && !name.starts_with('-') => {
+                    Ok(Command::DeleteSecret { name })
+                }
+                _ => Err(USAGE.to_string()),
+            },
+            Some(_) => Err(USAGE.to_string()),
+        },
+        _ => return None,
+    };
+    if let Some(extra) = args.next() {
+        return Some(Err(format!("unexpected argument {:?}\n{}", extra, USAGE)));
+    }
+    Some(command)
+}
+
+/// Sends `command` to the running instance and prints its reply. Returns the
+/// process exit code.
+pub fn run(identifier: &str, command: Result<Command, String>) -> i32 {
+    let command = match command {
+        Ok(command) => command,
+        Err(message) => {
+            eprintln!("{}", message);
+            return 2;
+        }
+    };
+    match send(identifier, command) {
+        Ok(code) => code,
+        Err(err) => {
+            eprintln!("Failed to talk to the running Eliza Desktop: {}", err);
+            1
+        }
+    }
+}
+
+fn send(identifier: &str, command: Command) -> io::Result<i32> {
+    let Some(mut stream) = instance::connect_running(identifier)? else {
+        eprintln!("Eliza Desktop is not running");
+        return Ok(1);
+    };
+    let request = match command {
+        Command::Status => Request::Status,
+        Command::Logs { follow } => Request::Logs {
+            since: None,
+            follow,
+        },
+        Command::Stop => Request::Stop,
+        Command::Open { agent_id } => Request::Open { agent_id },
+        Command::CreateVault => {
+            let passphrase = read_secret("New vault passphrase: ")?;
+            let confirmation = read_secret("Repeat the passphrase: ")?;
+            Request::CreateVault {
+                passphrase: passphrase.to_string(),
+                confirmation: confirmation.to_string(),
+            }
+        }
+        Command::UnlockVault => Request::UnlockVault {
+            passphrase: read_secret("Vault passphrase: ")?.to_string(),
+        },
+        Command::LockVault => Request::LockVault,
+        Command::ListSecrets => Request::ListSecrets,
+        Command::SetSecret { name, inject } => {
+            let value = read_secret(&format!("Value of {}: ", name))?;
+            Request::SetSecret {
+                name,
+                value: value.to_string(),
+                inject,
+            }
+        }
+        Command::DeleteSecret { name } => Request::DeleteSecret { name },
+    };
+    let mut message = Zeroizing::new(
+        serde_json::to_string(&request)
+            .map_err(|err| io::Error::new(io::ErrorKind::InvalidData, err))?,
+    );
+    message.push('\n');
+    stream.write_all(message.as_bytes())?;
+
+    let mut stdout = io::stdout().lock();
+    for line in BufReader::new(stream).lines() {
+        let reply: Value = serde_json::from_str(&line?)
+            .map_err(|err| io::Error::new(io::ErrorKind::InvalidData, err))?;
+        match reply["type"].as_str() {
+            Some("log") => writeln!(stdout, "{}", text(&reply["line"]["text"]))?,
+            Some("status") => {
+                print_status(&mut stdout, &reply)?;
+                return Ok(0);
+            }
+            Some("opened") => {
+                writeln!(stdout, "Opened the chat in the app window")?;
+                return Ok(0);
+            }
+            Some("secrets") => {
+                print_secrets(&mut stdout, &reply["secrets"])?;
+                return Ok(0);
+            }
+            Some("done") => return Ok(0),
+            Some("error") => {
+                eprintln!("{}", text(&reply["message"]));
+                return Ok(1);
+            }
+            _ => {}
+        }
+    }
+    Ok(0)
+}
+
+fn print_status(out: &mut impl Write, reply: &Value) -> io::Result<()> {
+    let state = &reply["state"];
+    writeln!(out, "state: {}", text(&state["state"]))?;
+    if let Some(version) = state["version"].as_str() {
+        writeln!(out, "version: {}", version)?;
+    }
+    if let Some(agents_ready) = state["agentsReady"].as_bool() {
+        writeln!(out, "agents ready: {}", agents_ready)?;
+    }
+    if let Some(message) = state["error"]["message"].as_str() {
+        writeln!(out, "error: {}", message)?;
+    }
+    writeln!(out, "server: {}", text(&reply["server"]))?;
+    if reply["window"].as_bool() == Some(true) {
+        writeln!(out, "client: in the app window")?;
+    } else {
+        writeln!(out, "client: unavailable, it needs the app window")?;
+    }
+    if let Some(pid) = reply["pid"].as_u64() {
+        writeln!(out, "pid: {}", pid)?;
+    }
+    Ok(())
+}
+
+fn print_secrets(out: &mut impl Write, secrets: &Value) -> io::Result<()> {
+    let secrets = secrets.as_array().map(Vec::as_slice).unwrap_or_default();
+    if secrets.is_empty() {
+        return writeln!(out, "The vault holds no secrets");
+    }
+    for secret in secrets {
+        if secret["inject"].as_bool() == Some(true) {
+            writeln!(out, "{}", text(&secret["name"]))?;
+        } else {
+            writeln!(out, "{} (not passed to the server)", text(&secret["name"]))?;
+        }
+    }
+    Ok(())
+}
+
+/// Reads one line from stdin without the trailing newline. On a terminal it
+/// shows `prompt` on stderr and hides what is typed.
+fn read_secret(prompt: &str) -> io::Result<Zeroizing<String>> {
+    let stdin = io::stdin();
+    let terminal = stdin.is_terminal();
+    if terminal {
+        eprint!("{}", prompt);
+        io::stderr().flush()?;
+    }
+    let mut line = Zeroizing::new(String::new());
+    {
+        // The Windows console keeps echoing; the value still stays out of
+        // argv.
+        #[cfg(unix)]
+        let _echo = if terminal { EchoOff::new() } else { None };
+        stdin.lock().read_line(&mut line)?;
+    }
+    if terminal {
+        eprintln!();
+    }
+    let len = line.trim_end_matches(['\r', '\n']).len();
+    line.truncate(len);
+    Ok(line)
+}
+
+/// Turns terminal echo off on stdin until dropped.
+#[cfg(unix)]
+struct EchoOff(libc::termios);
+
+#[cfg(unix)]
+impl EchoOff {
+    fn new() -> Option<Self> {
+        // SAFETY: termios is plain data that tcgetattr fills in; it is only
+        // used when the call succeeds.
+        let mut original: libc::termios = unsafe { std::mem::zeroed() };
+        // SAFETY: the pointer is to a live termios for the duration of the call.
+        if unsafe { libc::tcgetattr(libc::STDIN_FILENO, &mut original) } != 0 {
+            return None;
+        }
+        let mut hidden = original;
+        hidden.c_lflag &= !libc::ECHO;
+        // SAFETY: as above; `hidden` is a copy of valid settings.
+        if unsafe { libc::tcsetattr(libc::STDIN_FILENO, libc::TCSAFLUSH, &hidden) } != 0 {
+            return None;
+        }
+        Some(EchoOff(original))
+    }
+}
+
+#[cfg(unix)]
+impl Drop for EchoOff {
+    fn drop(&mut self) {
+        // SAFETY: restores the settings tcgetattr returned.
+        unsafe { libc::tcsetattr(libc::STDIN_FILENO, libc::TCSAFLUSH, &self.0) };
+    }
+}
+
+fn text(value: &Value) -> &str {
+    value.as_str().unwrap_or_default()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse_args(args: &[&str]) -> Option<Result<Command, String>> {
+        parse(args.iter().map(|arg| arg.to_string()))
+    }
+
+    #[test]
+    fn parses_subcommands() {
+        assert_eq!(parse_args(&["status"]), Some(Ok(Command::Status)));
+        assert_eq!(parse_args(&["stop"]), Some(Ok(Command::Stop)));
+        assert_eq!(
+            parse_args(&["logs"]),
+            Some(Ok(Command::Logs { follow: false }))
+        );
+        assert_eq!(
+            parse_args(&["logs", "-f"]),
+            Some(Ok(Command::Logs { follow: true }))
+        );
+        assert_eq!(
+            parse_args(&["open", "7f3c"]),
+            Some(Ok(Command::Open {
+                agent_id: "7f3c".into()
+            }))
+        );
+        assert_eq!(
+            parse_args(&["vault", "create"]),
+            Some(Ok(Command::CreateVault))
+        );
+        assert_eq!(
+            parse_args(&["vault", "unlock"]),
+            Some(Ok(Command::UnlockVault))
+        );
+        assert_eq!(parse_args(&["secrets"]), Some(Ok(Command::ListSecrets)));
+        assert_eq!(
+            parse_args(&["secrets", "set", "OPENAI_API_KEY"]),
+            Some(Ok(Command::SetSecret {
+                name: "OPENAI_API_KEY".into(),
+                inject: true
+            }))
+        );
+        assert_eq!(
+            parse_args(&["secrets", "set", "WALLET_KEY", "--no-inject"]),
+            Some(Ok(Command::SetSecret {
+                name: "WALLET_KEY".into(),
+                inject: false
+            }))
+        );
+        assert_eq!(
+            parse_args(&["secrets", "delete", "WALLET_KEY"]),
+            Some(Ok(Command::DeleteSecret {
+                name: "WALLET_KEY".into()
+            }))
+        );
+    }
+
+    #[test]
+    fn launches_the_app_without_a_subcommand() {
+        assert_eq!(parse_args(&[]), None);
+        // Flags the OS or a desktop entry may pass to the app itself.
+        assert_eq!(parse_args(&["--headless"]), None);
+        assert_eq!(parse_args(&["-psn_0_12345"]), None);
+        assert_eq!(parse_args(&["Status"]), None);
+    }
+
+    #[test]
+    fn rejects_bad_arguments() {
+        for args in [
+            &["logs", "--bogus"][..],
+            &["logs", "--follow", "extra"],
+            &["open"],
+            &["open", ""],
+            &["open", "--follow"],
+            &["open", "7f3c", "extra"],
+            &["status", "--json"],
+            &["stop", "now"],
+            &["vault"],
+            &["vault", "open"],
+            &["vault", "lock", "now"],
+            &["secrets", "show"],
+            &["secrets", "set"],
+            &["secrets", "set", "--no-inject"],
+            // The value is never taken from the command line.
+            &["secrets", "set", "OPENAI_API_KEY", "sk-123"],
+            &["secrets", "delete"],
+            &["secrets", "delete", "A", "B"],
+        ] {
+            let err = parse_args(args).unwrap().unwrap_err();
+            assert!(err.contains(USAGE), "{:?}: {}", args, err);
+        }
+    }
+}
@@ -0,0 +1,48 @@
+use std::collections::HashMap;
+use tauri::utils::config::{Csp, CspDirectiveSources};
+
+use crate::config::{self, ConfigError};
+
+/// Directives the Eliza client needs its origin in. The rest of the policy
+/// comes from `tauri.conf.json` unchanged.
+const CLIENT_DIRECTIVES: &[&str] = &["img-src", "connect-src", "style-src", "script-src"];
+
+/// Returns `base` with each of `origins` allowed in [`CLIENT_DIRECTIVES`].
+/// `connect-src` also gets the matching WebSocket origin.
+pub fn with_origins(base: Option<Csp>, origins: &[String]) -> Result<Csp, ConfigError> {
+    let mut directives: HashMap<String, CspDirectiveSources> =
+        base.map(Into::into).unwrap_or_default();
+    if !directives.contains_key("default-src") {
+        directives.insert(
+            "default-src".into(),
+            CspDirectiveSources::List(vec!["'self'".into()]),
+        );
+    }
+
+    for origin in origins {
+        let origin = config::validate_origin(origin)?;
+        for name in CLIENT_DIRECTIVES {
+            let sources = directives
+                .entry(name.to_string())
+                .or_insert_with(|| CspDirectiveSources::List(vec!["'self'".into()]));
+            push_unique(sources, &origin);
+            if *name == "connect-src" {
+                push_unique(sources, &websocket_origin(&origin));
+            }
+        }
+    }
+    Ok(Csp::DirectiveMap(directives))
+}
+
+fn websocket_origin(origin: &str) -> String {
+    match origin.strip_prefix("https://") {
+        Some(rest) => format!("wss://{}", rest),
+        None => format!("ws://{}", origin.trim_start_matches("http://")),
+    }
+}
+
+fn push_unique(sources: &mut CspDirectiveSources, source: &str) {
+    if !sources.contains(source) {
+        sources.push(source);
+    }
+}
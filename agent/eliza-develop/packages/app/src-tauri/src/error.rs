@@ -0,0 +1,185 @@
+use serde::Serialize;
+use std::fmt;
+use std::io;
+
+use crate::config::{self, ConfigError};
+use crate::supervisor::ExitKind;
+
+/// Everything that can keep the Eliza server from running. Sent to the
+/// webview as an [`ErrorReport`].
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+#[serde(
+    tag = "kind",
+    rename_all = "camelCase",
+    rename_all_fields = "camelCase"
+)]
+pub enum AppError {
+    BinaryNotFound {
+        binary: String,
+    },
+    SpawnFailed {
+        message: String,
+    },
+    PortConflict {
+        port: u16,
+        reason: String,
+    },
+    HealthTimeout {
+        timeout_secs: u64,
+    },
+    ServerExited {
+        exit: ExitKind,
+    },
+    CrashLoop {
+        crashes: usize,
+        window_secs: u64,
+    },
+    Config {
+        key: String,
+        message: String,
+    },
+    /// Messages of the preflight checks that failed.
+    PreflightFailed {
+        failures: Vec<String>,
+    },
+    /// The server at `url` rejected the app's API key.
+    Unauthorized {
+        url: String,
+    },
+    /// The remote server at `url` cannot be used.
+    RemoteUnavailable {
+        url: String,
+        reason: String,
+    },
+}
+
+/// An [`AppError`] with the text the wrapper shows for it.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ErrorReport {
+    #[serde(flatten)]
+    pub error: AppError,
+    pub message: String,
+    pub hint: String,
+}
+
+impl AppError {
+    /// Maps an error from spawning `binary`.
+    pub fn spawn(binary: &str, err: io::Error) -> Self {
+        if err.kind() == io::ErrorKind::NotFound {
+            AppError::BinaryNotFound {
+                binary: binary.to_string(),
+            }
+        } else {
+            AppError::SpawnFailed {
+                message: err.to_string(),
+            }
+        }
+    }
+
+    /// Whether restarting the server could make this go away on its own.
+    pub fn is_retryable(&self) -> bool {
+        matches!(
+            self,
+            AppError::SpawnFailed { .. }
+                | AppError::HealthTimeout { .. }
+                | AppError::ServerExited { .. }
+        )
+    }
+
+    pub fn hint(&self) -> String {
+        match self {
+            AppError::BinaryNotFound { binary } => format!(
+                "Install the ElizaOS CLI (`bun install -g @elizaos/cli`) and make sure `{}` is on your PATH.",
+                binary
+            ),
+            AppError::SpawnFailed { .. } => {
+                "Check that the ElizaOS CLI is installed correctly and can be run from a terminal."
+                    .into()
+            }
+            AppError::PortConflict { port, .. } => format!(
+                "Stop the program using port {} or set SERVER_PORT to a free port.",
+                port
+            ),
+            AppError::HealthTimeout { .. } => {
+                "The server started but never became healthy. Check the server logs for errors."
+                    .into()
+            }
+            AppError::ServerExited { .. } => "Check the server logs for the cause of the exit.".into(),
+            AppError::CrashLoop { .. } => {
+                "The server keeps crashing. Check the server logs, fix the cause and retry.".into()
+            }
+            AppError::Config { key, .. } => format!("Fix the value of {} and retry.", key),
+            AppError::PreflightFailed { .. } => {
+                "Fix the problems found by the preflight checks and retry.".into()
+            }
+            AppError::Unauthorized { .. } => format!(
+                "Set {} to the token the server was started with.",
+                config::AUTH_TOKEN_ENV
+            ),
+            AppError::RemoteUnavailable { url, .. } => format!(
+                "Check that the Eliza server at {} is running and reachable from this machine.",
+                url
+            ),
+        }
+    }
+
+    pub fn report(&self) -> ErrorReport {
+        ErrorReport {
+            error: self.clone(),
+            message: self.to_string(),
+            hint: self.hint(),
+        }
+    }
+}
+
+impl fmt::Display for AppError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            AppError::BinaryNotFound { binary } => {
+                write!(f, "Could not find the `{}` executable", binary)
+            }
+            AppError::SpawnFailed { message } => {
+                write!(f, "Failed to start Eliza server: {}", message)
+            }
+            AppError::PortConflict { port, reason } => {
+                write!(f, "Port {} is held by another process: {}", port, reason)
+            }
+            AppError::HealthTimeout { timeout_secs } => write!(
+                f,
+                "Eliza server did not become healthy within {} seconds",
+                timeout_secs
+            ),
+            AppError::ServerExited { exit } => write!(f, "Eliza server {}", exit),
+            AppError::CrashLoop {
+                crashes,
+                window_secs,
+            } => write!(
+                f,
+                "Eliza server crashed {} times in {} seconds",
+                crashes, window_secs
+            ),
+            AppError::Config { key, message } => write!(f, "Invalid {}: {}", key, message),
+            AppError::PreflightFailed { failures } => {
+                write!(f, "Preflight checks failed: {}", failures.join("; "))
+            }
+            AppError::Unauthorized { url } => {
+                write!(f, "The Eliza server at {} rejected the API key", url)
+            }
+            AppError::RemoteUnavailable { url, reason } => {
+                write!(f, "Cannot use the Eliza server at {}: {}", url, reason)
+            }
+        }
+    }
+}
+
+impl std::error::Error for AppError {}
+
+impl From<ConfigError> for AppError {
+    fn from(err: ConfigError) -> Self {
+        AppError::Config {
+            key: err.key.to_string(),
+            message: err.message,
+        }
+    }
+}
@@ -0,0 +1,106 @@
+use std::env;
+use std::thread;
+use std::time::Duration;
+
+use crate::host::Host;
+use crate::state::{self, ServerState};
+use crate::supervisor::{self, ExitKind};
+use crate::{config, instance, log_files, paths, pid_file, settings, signals};
+
+/// Runs the supervisor without opening a window.
+pub const FLAG: &str = "--headless";
+
+const POLL_INTERVAL: Duration = Duration::from_millis(250);
+
+pub fn requested() -> bool {
+    env::args().skip(1).any(|arg| arg == FLAG)
+}
+
+/// Starts and supervises the server until it stops for good or the process
+/// receives SIGINT or SIGTERM, using the same supervisor, log files and PID
+/// file as the windowed app. In remote mode it only monitors the server.
+/// Returns the process exit code: 0 after a requested shutdown, otherwise
+/// the server's own status.
+pub fn run(identifier: &str) -> i32 {
+    match instance::acquire(identifier) {
+        Ok(instance::Instance::Primary(guard)) => instance::listen(Host::Headless, guard),
+        Ok(instance::Instance::Forwarded) => {
+            eprintln!("Eliza Desktop is already running");
+            return 1;
+        }
+        Err(err) => eprintln!("Failed to check for a running instance: {}", err),
+    }
+    if let Err(err) = signals::install() {
+        eprintln!("Failed to install signal handlers: {}", err);
+    }
+
+    match paths::app_log_dir(identifier) {
+        Some(dir) => log_files::init(dir.join("server"), settings::current().retention_policy()),
+        None => eprintln!("Server logs will not be written to disk: no log directory"),
+    }
+    match paths::app_data_dir(identifier) {
+        Some(dir) => pid_file::init(dir.join("server.pid")),
+        None => eprintln!("Server PID file will not be written: no data directory"),
+    }
+    if let Some(orphan) = pid_file::detect_orphan() {
+        eprintln!(
+            "Eliza server pid {} from an earlier session is still running on port {}",
+            orphan.pid, orphan.port
+        );
+    }
+
+    let host = Host::Headless;
+    crate::start_or_attach(&host, crate::resolve_server(true));
+
+    let mut last_state = None;
+    loop {
+        if let Some(signal) = signals::received() {
+            println!(
+                "Received signal {}, shutting down; a second signal exits at once",
+                signal
+            );
+            let _control = supervisor::control();
+            supervisor::stop();
+            return 0;
+        }
+        let current = state::current();
+        if last_state.as_ref() != Some(&current) {
+            println!("Eliza server state: {:?}", current);
+            last_state = Some(current.clone());
+        }
+        match current {
+            ServerState::Ready { external: true, .. } if !config::current().is_remote() => {
+                eprintln!(
+                    "An Eliza server is already running on port {}; there is nothing to supervise",
+                    config::current().port
+                );
+                return 1;
+            }
+            ServerState::Stopped if supervisor::stop_requested() => return 0,
+            ServerState::Stopped => {
+                return supervisor::last_exit().map_or(0, exit_code);
+            }
+            ServerState::Crashed {
+                error,
+                will_restart: false,
+            } => {
+                eprintln!("{}. {}", error.message, error.hint);
+                return match supervisor::last_exit() {
+                    Some(exit) if exit.is_crash() => exit_code(exit),
+                    _ => 1,
+                };
+            }
+            _ => {}
+        }
+        thread::sleep(POLL_INTERVAL);
+    }
+}
+
+/// Shell-style exit code for how the server ended.
+fn exit_code(exit: ExitKind) -> i32 {
+    match exit {
+        ExitKind::Clean => 0,
+        ExitKind::Code { code } => code,
+        ExitKind::Signal { signal } => 128 + signal,
+    }
+}
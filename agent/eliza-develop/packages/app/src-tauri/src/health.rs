@@ -0,0 +1,302 @@
+use serde::{Deserialize, Serialize};
+use std::io;
+use std::time::Duration;
+
+use crate::config;
+
+/// How long a single probe request may take before the server is considered
+/// still starting.
+const PROBE_TIMEOUT: Duration = Duration::from_secs(2);
+
+/// Header the server's `apiKeyAuthMiddleware` reads the token from.
+pub const API_KEY_HEADER: &str = "X-API-KEY";
+
+/// What is listening on the server port, as far as we can tell.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+#[serde(
+    tag = "state",
+    rename_all = "camelCase",
+    rename_all_fields = "camelCase"
+)]
+pub enum ProbeResult {
+    /// Nothing accepts connections on the port.
+    NotRunning,
+    /// Something accepts connections but does not answer the Eliza routes
+    /// yet, e.g. while the server is still booting.
+    Starting,
+    /// `/api/server/ping` and `/api/server/health` answered like an Eliza
+    /// server. `agents_ready` is false while the health route reports no
+    /// loaded agents.
+    Healthy { version: String, agents_ready: bool },
+    /// An Eliza server answered but requires an API key we do not have or
+    /// that it does not accept.
+    Unauthorized,
+    /// Another HTTP service owns the port.
+    Foreign { reason: String },
+}
+
+/// Response of `GET /api/server/ping`.
+#[derive(Debug, Deserialize)]
+struct Ping {
+    pong: bool,
+    timestamp: u64,
+}
+
+/// Response of `GET /api/server/health`, returned with 200 or 503.
+#[derive(Debug, Deserialize)]
+struct Health {
+    status: String,
+    version: String,
+    dependencies: HealthDependencies,
+}
+
+#[derive(Debug, Deserialize)]
+struct HealthDependencies {
+    agents: String,
+}
+
+/// Response of `GET /api/system/version`.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SystemVersion {
+    pub version: String,
+    pub source: String,
+    pub timestamp: String,
+    pub environment: String,
+    /// Seconds since the server process started.
+    pub uptime: f64,
+    pub error: Option<String>,
+}
+
+/// Probes the server at `base_url` (e.g. `http://127.0.0.1:3000`).
+pub fn probe(base_url: &str) -> ProbeResult {
+    let agent = agent();
+
+    let mut ping = match get(&agent, format!("{}/api/server/ping", base_url)).call() {
+        Ok(response) => response,
+        Err(err) => return classify_error(err),
+    };
+    let status = ping.status().as_u16();
+    if status == 401 {
+        return ProbeResult::Unauthorized;
+    }
+    if status >= 500 {
+        return ProbeResult::Starting;
+    }
+    if status != 200 {
+        return foreign(format!("/api/server/ping answered with HTTP {}", status));
+    }
+    match ping.body_mut().read_json::<Ping>() {
+        Ok(ping) if ping.pong && ping.timestamp > 0 => {}
+        Ok(_) => return foreign("/api/server/ping did not answer pong"),
+        Err(_) => return foreign("/api/server/ping did not return the Eliza ping payload"),
+    }
+
+    let mut health = match get(&agent, format!("{}/api/server/health", base_url)).call() {
+        Ok(response) => response,
+        Err(err) => {
+            return match classify_error(err) {
+                ProbeResult::NotRunning => ProbeResult::NotRunning,
+                _ => ProbeResult::Starting,
+            }
+        }
+    };
+    let status = health.status().as_u16();
+    if status != 200 && status != 503 {
+        return ProbeResult::Starting;
+    }
+    match health.body_mut().read_json::<Health>() {
+        Ok(health) => ProbeResult::Healthy {
+            agents_ready: status == 200
+                && health.status == "OK"
+                && health.dependencies.agents == "healthy",
+            version: health.version,
+        },
+        Err(_) => foreign("/api/server/health did not return the Eliza health payload"),
+    }
+}
+
+/// Fetches the version information the server reports about itself. The
+/// route answers 500 with the payload when it could not read its version.
+pub fn system_version(base_url: &str) -> Result<SystemVersion, String> {
+    let mut response = get(&agent(), format!("{}/api/system/version", base_url))
+        .call()
+        .map_err(|err| err.to_string())?;
+    let status = response.status().as_u16();
+    if status == 401 {
+        return Err("the server rejected the API key".into());
+    }
+    if status != 200 && status != 500 {
+        return Err(format!("/api/system/version answered with HTTP {}", status));
+    }
+    response
+        .body_mut()
+        .read_json::<SystemVersion>()
+        .map_err(|err| err.to_string())
+}
+
+fn agent() -> ureq::Agent {
+    ureq::Agent::config_builder()
+        .timeout_global(Some(PROBE_TIMEOUT))
+        .http_status_as_error(false)
+        .build()
+        .into()
+}
+
+/// A GET request carrying the configured API key, if there is one.
+fn get(agent: &ureq::Agent, url: String) -> ureq::RequestBuilder<ureq::typestate::WithoutBody> {
+    let request = agent.get(url);
+    match config::current().auth_token {
+        Some(token) => request.header(API_KEY_HEADER, token),
+        None => request,
+    }
+}
+
+fn classify_error(err: ureq::Error) -> ProbeResult {
+    match err {
+        ureq::Error::ConnectionFailed => ProbeResult::NotRunning,
+        ureq::Error::Io(ref io_err)
+            if matches!(
+                io_err.kind(),
+                io::ErrorKind::ConnectionRefused | io::ErrorKind::AddrNotAvailable
+            ) =>
+        {
+            ProbeResult::NotRunning
+        }
+        ureq::Error::Timeout(_) | ureq::Error::Io(_) => ProbeResult::Starting,
+        ureq::Error::Protocol(_) => foreign("the port does not speak HTTP"),
+        err => foreign(err.to_string()),
+    }
+}
+
+fn foreign(reason: impl Into<String>) -> ProbeResult {
+    ProbeResult::Foreign {
+        reason: reason.into(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::{BufRead, BufReader, Write};
+    use std::net::{Ipv4Addr, TcpListener};
+    use std::thread;
+
+    const PING: &str = r#"{"pong":true,"timestamp":1700000000000}"#;
+    const HEALTH: &str = r#"{"status":"OK","version":"1.2.3","dependencies":{"agents":"healthy"}}"#;
+
+    /// A server that answers each path with the given status and body, and
+    /// 404 for anything else. Returns its base URL.
+    fn serve(routes: &[(&'static str, u16, &'static str)]) -> String {
+        let routes = routes.to_vec();
+        serve_raw(move |path| {
+            let (status, body) = routes
+                .iter()
+                .find(|(route, _, _)| *route == path)
+                .map_or((404, "{}"), |(_, status, body)| (*status, *body));
+            format!(
+                "HTTP/1.1 {} X\r\ncontent-type: application/json\r\n\
+                 content-length: {}\r\nconnection: close\r\n\r\n{}",
+                status,
+                body.len(),
+                body
+            )
+        })
+    }
+
+    /// A server that writes `answer(path)` to each connection verbatim.
+    fn serve_raw(answer: impl Fn(&str) -> String + Send + 'static) -> String {
+        let listener = TcpListener::bind((Ipv4Addr::LOCALHOST, 0)).unwrap();
+        let addr = listener.local_addr().unwrap();
+        thread::spawn(move || {
+            for stream in listener.incoming() {
+                let mut stream = stream.unwrap();
+                let mut reader = BufReader::new(stream.try_clone().unwrap());
+                let mut request_line = String::new();
+                reader.read_line(&mut request_line).unwrap();
+                let mut line = String::new();
+                while reader.read_line(&mut line).unwrap() > 2 {
+                    line.clear();
+                }
+                let path = request_line.split(' ').nth(1).unwrap_or_default();
+                let _ = stream.write_all(answer(path).as_bytes());
+            }
+        });
+        format!("http://{}", addr)
+    }
+
+    fn is_foreign(result: &ProbeResult) -> bool {
+        matches!(result, ProbeResult::Foreign { .. })
+    }
+
+    #[test]
+    fn recognises_a_healthy_server() {
+        let url = serve(&[
+            ("/api/server/ping", 200, PING),
+            ("/api/server/health", 200, HEALTH),
+        ]);
+        assert_eq!(
+            probe(&url),
+            ProbeResult::Healthy {
+                version: "1.2.3".into(),
+                agents_ready: true,
+            }
+        );
+    }
+
+    #[test]
+    fn reports_agents_not_ready_while_health_answers_503() {
+        let url = serve(&[
+            ("/api/server/ping", 200, PING),
+            (
+                "/api/server/health",
+                503,
+                r#"{"status":"DEGRADED","version":"1.2.3","dependencies":{"agents":"no_agents"}}"#,
+            ),
+        ]);
+        assert_eq!(
+            probe(&url),
+            ProbeResult::Healthy {
+                version: "1.2.3".into(),
+                agents_ready: false,
+            }
+        );
+    }
+
+    #[test]
+    fn classifies_ping_answers() {
+        let url = serve(&[("/api/server/ping", 401, "{}")]);
+        assert_eq!(probe(&url), ProbeResult::Unauthorized);
+
+        for status in [500, 502, 503] {
+            let url = serve(&[("/api/server/ping", status, "{}")]);
+            assert_eq!(probe(&url), ProbeResult::Starting, "HTTP {}", status);
+        }
+
+        let url = serve(&[]);
+        assert!(is_foreign(&probe(&url)), "404 should be foreign");
+
+        for body in [
+            r#"{"pong":false,"timestamp":1}"#,
+            r#"{"ok":true}"#,
+            "<html>",
+        ] {
+            let url = serve(&[("/api/server/ping", 200, body)]);
+            assert!(is_foreign(&probe(&url)), "{} should be foreign", body);
+        }
+    }
+
+    #[test]
+    fn takes_a_port_that_does_not_speak_http_for_foreign() {
+        let url = serve_raw(|_| "SSH-2.0-OpenSSH_9.6\r\n".into());
+        assert!(is_foreign(&probe(&url)), "{:?}", probe(&url));
+    }
+
+    #[test]
+    fn finds_nothing_on_a_closed_port() {
+        let listener = TcpListener::bind((Ipv4Addr::LOCALHOST, 0)).unwrap();
+        let addr = listener.local_addr().unwrap();
+        drop(listener);
+        assert_eq!(probe(&format!("http://{}", addr)), ProbeResult::NotRunning);
+    }
+}
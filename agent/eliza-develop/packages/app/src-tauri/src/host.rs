@@ -0,0 +1,32 @@
+use serde::Serialize;
+use tauri::{AppHandle, Emitter};
+
+/// What the server is supervised for: the windowed app, whose webview is
+/// sent every event, or headless mode, where events have no listener.
+#[derive(Clone)]
+pub enum Host {
+    Window(AppHandle),
+    Headless,
+}
+
+impl Host {
+    pub fn emit<S: Serialize + Clone>(&self, event: &str, payload: S) -> tauri::Result<()> {
+        match self {
+            Host::Window(app) => app.emit(event, payload),
+            Host::Headless => Ok(()),
+        }
+    }
+
+    pub fn app(&self) -> Option<&AppHandle> {
+        match self {
+            Host::Window(app) => Some(app),
+            Host::Headless => None,
+        }
+    }
+}
+
+impl From<AppHandle> for Host {
+    fn from(app: AppHandle) -> Self {
+        Host::Window(app)
+    }
+}
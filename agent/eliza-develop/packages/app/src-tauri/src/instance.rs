@@ -0,0 +1,548 @@
+use serde::{Deserialize, Serialize};
+use std::env;
+use std::fs::{self, File, OpenOptions, TryLockError};
+use std::io::{self, BufRead, BufReader, Write};
+use std::net::{Ipv4Addr, SocketAddr, TcpListener, TcpStream};
+use std::path::{Path, PathBuf};
+use std::thread;
+use std::time::{Duration, Instant};
+use tauri::{AppHandle, Manager};
+use zeroize::Zeroizing;
+
+use crate::host::Host;
+use crate::logs::{self, LogLine};
+use crate::state::{self, ServerState};
+use crate::vault::{self, SecretInfo};
+use crate::{auth, config, paths, proxy, supervisor, SERVER_PROCESS};
+
+/// Event carrying the [`SecondInstance`] of a launch that was forwarded here.
+pub const INSTANCE_EVENT: &str = "second-instance";
+
+/// Event asking the webview to show an agent's chat, carrying its id.
+pub const OPEN_AGENT_EVENT: &str = "open-agent";
+
+/// How often `logs --follow` checks for new lines.
+const FOLLOW_INTERVAL: Duration = Duration::from_millis(250);
+
+/// How long a second launch waits for the first one to publish its port.
+const PORT_WAIT: Duration = Duration::from_secs(5);
+const PORT_POLL_INTERVAL: Duration = Duration::from_millis(100);
+const CONNECT_TIMEOUT: Duration = Duration::from_secs(2);
+
+/// Messages sent to the running instance, one JSON object per line, after a
+/// line with the instance's secret.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(
+    tag = "type",
+    rename_all = "camelCase",
+    rename_all_fields = "camelCase"
+)]
+pub enum Request {
+    /// Another launch of the app; the running instance takes its place.
+    Activate {
+        args: Vec<String>,
+        cwd: PathBuf,
+    },
+    Status,
+    /// Buffered server output after `since`, then new lines as they arrive
+    /// if `follow` is set.
+    Logs {
+        since: Option<u64>,
+        follow: bool,
+    },
+    /// Stops the server the instance supervises.
+    Stop,
+    /// Shows an agent's chat in the main window.
+    Open {
+        agent_id: String,
+    },
+    /// Creates the secrets vault; `confirmation` is the passphrase typed
+    /// again.
+    CreateVault {
+        passphrase: String,
+        confirmation: String,
+    },
+    UnlockVault {
+        passphrase: String,
+    },
+    LockVault,
+    ListSecrets,
+    /// Adds or replaces a secret in the unlocked vault.
+    SetSecret {
+        name: String,
+        value: String,
+        inject: bool,
+    },
+    DeleteSecret {
+        name: String,
+    },
+}
+
+/// Answers to a [`Request`], one JSON object per line. `Activate` gets none.
+#[derive(Debug, Clone, Serialize)]
+#[serde(
+    tag = "type",
+    rename_all = "camelCase",
+    rename_all_fields = "camelCase"
+)]
+pub enum Reply {
+    Status {
+        state: ServerState,
+        /// Where the server's API is. The client is only served to the app
+        /// window, which adds the API key.
+        server: String,
+        /// Whether there is an app window to show the client in.
+        window: bool,
+        pid: Option<u32>,
+    },
+    Log {
+        line: LogLine,
+    },
+    /// An agent's chat was opened in the app window.
+    Opened,
+    /// The vault's secrets after a secrets request, without their values.
+    Secrets {
+        secrets: Vec<SecretInfo>,
+    },
+    Done,
+    Error {
+        message: String,
+    },
+}
+
+/// Arguments of a launch that was handed over to this instance.
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SecondInstance {
+    pub args: Vec<String>,
+    pub cwd: PathBuf,
+}
+
+/// Held by the first instance for as long as it runs. Dropping it releases
+/// the lock.
+pub struct InstanceGuard {
+    _lock: File,
+    listener: TcpListener,
+    secret: String,
+}
+
+/// The outcome of [`acquire`].
+pub enum Instance {
+    /// This is the only instance.
+    Primary(InstanceGuard),
+    /// Another instance holds the lock; this launch was handed to it if it
+    /// could be reached.
+    Forwarded,
+}
+
+/// Takes the single-instance lock, or forwards this launch to the instance
+/// holding it. `id` is the app identifier, used to name the lock files.
+pub fn acquire(id: &str) -> io::Result<Instance> {
+    let dir = lock_dir();
+    create_private_dir(&dir)?;
+    let lock_path = dir.join(format!("{}.lock", id));
+    let port_path = dir.join(format!("{}.port", id));
+
+    let lock = OpenOptions::new()
+        .create(true)
+        .truncate(false)
+        .write(true)
+        .open(&lock_path)?;
+    match lock.try_lock() {
+        Ok(()) => {}
+        Err(TryLockError::WouldBlock) => {
+            // Even if the running instance cannot be reached, this launch
+            // must not start a second server.
+            if let Err(err) = forward(&port_path) {
+                eprintln!("Failed to reach the running instance: {}", err);
+            }
+            return Ok(Instance::Forwarded);
+        }
+        Err(TryLockError::Error(err)) => return Err(err),
+    }
+
+    let listener = TcpListener::bind((Ipv4Addr::LOCALHOST, 0))?;
+    // Any local user can connect to the port, so requests must carry a
+    // secret only this user can read. Written next to the lock rather than
+    // into it, since Windows does not allow reading a locked file.
+    let secret = auth::generate()?;
+    let port = listener.local_addr()?.port();
+    paths::write_private(&port_path, format!("{}\n{}\n", port, secret).as_bytes())?;
+    Ok(Instance::Primary(InstanceGuard {
+        _lock: lock,
+        listener,
+        secret,
+    }))
+}
+
+/// Serves forwarded launches and command-line requests until the app exits.
+/// Each connection gets its own thread, since `logs --follow` stays open.
+pub fn listen(host: Host, guard: InstanceGuard) {
+    let spawned = thread::Builder::new()
+        .name("eliza-instance".into())
+        .spawn(move || {
+            let secret = guard.secret;
+            for stream in guard.listener.incoming() {
+                let stream = match stream {
+                    Ok(stream) => stream,
+                    Err(err) => {
+                        eprintln!("Failed to accept instance connection: {}", err);
+                        continue;
+                    }
+                };
+                let host = host.clone();
+                let secret = secret.clone();
+                let spawned = thread::Builder::new()
+                    .name("eliza-instance-client".into())
+                    .spawn(move || {
+                        if let Err(err) = handle(&host, &secret, stream) {
+                            eprintln!("Failed to handle instance request: {}", err);
+                        }
+                    });
+                if let Err(err) = spawned {
+                    eprintln!("Failed to spawn eliza-instance-client thread: {}", err);
+                }
+            }
+        });
+    if let Err(err) = spawned {
+        eprintln!("Failed to spawn eliza-instance thread: {}", err);
+    }
+}
+
+fn handle(host: &Host, secret: &str, stream: TcpStream) -> io::Result<()> {
+    let mut reader = BufReader::new(stream.try_clone()?);
+    let mut writer = stream;
+    let mut line = String::new();
+    reader.read_line(&mut line)?;
+    if !same_secret(line.trim_end(), secret) {
+        return Err(io::Error::new(
+            io::ErrorKind::PermissionDenied,
+            "request without the instance secret",
+        ));
+    }
+    line.clear();
+    reader.read_line(&mut line)?;
+    let request: Request = serde_json::from_str(&line)
+        .map_err(|err| io::Error::new(io::ErrorKind::InvalidData, err))?;
+    match request {
+        Request::Activate { args, cwd } => {
+            println!("Another launch was forwarded to this instance");
+            if let Some(app) = host.app() {
+                focus_main_window(app);
+            }
+            if let Err(err) = host.emit(INSTANCE_EVENT, SecondInstance { args, cwd }) {
+                eprintln!("Failed to emit {} event: {}", INSTANCE_EVENT, err);
+            }
+            Ok(())
+        }
+        Request::Status => {
+            let pid = SERVER_PROCESS
+                .lock()
+                .expect("SERVER_PROCESS mutex should not be poisoned")
+                .as_ref()
+                .map(|child| child.id());
+            let reply = Reply::Status {
+                state: state::current(),
+                server: config::current().api_url(),
+                window: host.app().is_some(),
+                pid,
+            };
+            send(&mut writer, &reply)
+        }
+        Request::Logs { since, follow } => {
+            let mut since = since;
+            loop {
+                // A failed write means the client went away.
+                for line in logs::lines_since(since) {
+                    since = Some(line.seq);
+                    send(&mut writer, &Reply::Log { line })?;
+                }
+                if !follow {
+                    return send(&mut writer, &Reply::Done);
+                }
+                // Without new lines nothing is written, so check directly.
+                if disconnected(&writer)? {
+                    return Ok(());
+                }
+                thread::sleep(FOLLOW_INTERVAL);
+            }
+        }
+        Request::Stop => {
+            println!("Stopping Eliza server on request from the command line");
+            let reply = {
+                let _control = supervisor::control();
+                match crate::stop_supervised(host) {
+                    Ok(()) => Reply::Done,
+                    Err(message) => Reply::Error { message },
+                }
+            };
+            send(&mut writer, &reply)
+        }
+        Request::Open { agent_id } => {
+            let reply = if !is_agent_id(&agent_id) {
+                Reply::Error {
+                    message: format!("{:?} is not an agent id", agent_id),
+                }
+            } else if let Some(app) = host.app() {
+                let path = format!("/chat/{}", agent_id);
+                focus_main_window(app);
+                // The wrapper page opens the chat once the client loads.
+                if !proxy::open(app, &path) {
+                    if let Err(err) = host.emit(OPEN_AGENT_EVENT, &agent_id) {
+                        eprintln!("Failed to emit {} event: {}", OPEN_AGENT_EVENT, err);
+                    }
+                }
+                Reply::Opened
+            } else {
+                // The server needs the API key the app window's proxy adds,
+                // so there is no link a browser could open instead.
+                Reply::Error {
+                    message: "Chats open in the app window, and this instance runs without one"
+                        .into(),
+                }
+            };
+            send(&mut writer, &reply)
+        }
+        Request::CreateVault {
+            passphrase,
+            confirmation,
+        } => {
+            let passphrase = Zeroizing::new(passphrase);
+            let confirmation = Zeroizing::new(confirmation);
+            send(
+                &mut writer,
+                &done(vault::create(&passphrase, &confirmation)),
+            )
+        }
+        Request::UnlockVault { passphrase } => {
+            let passphrase = Zeroizing::new(passphrase);
+            send(&mut writer, &done(vault::unlock(&passphrase)))
+        }
+        Request::LockVault => {
+            vault::lock_vault();
+            send(&mut writer, &Reply::Done)
+        }
+        Request::ListSecrets => send(&mut writer, &secrets(vault::list_secrets())),
+        Request::SetSecret {
+            name,
+            value,
+            inject,
+        } => send(
+            &mut writer,
+            &secrets(vault::set_secret(name, value, inject)),
+        ),
+        Request::DeleteSecret { name } => send(&mut writer, &secrets(vault::delete_secret(name))),
+    }
+}
+
+fn done(result: Result<(), String>) -> Reply {
+    match result {
+        Ok(()) => Reply::Done,
+        Err(message) => Reply::Error { message },
+    }
+}
+
+fn secrets(result: Result<Vec<SecretInfo>, String>) -> Reply {
+    match result {
+        Ok(secrets) => Reply::Secrets { secrets },
+        Err(message) => Reply::Error { message },
+    }
+}
+
+/// Compares in constant time, so the secret cannot be guessed byte by byte.
+fn same_secret(given: &str, secret: &str) -> bool {
+    given.len() == secret.len()
+        && given
+            .bytes()
+            .zip(secret.bytes())
+            .fold(0, |diff, (a, b)| diff | (a ^ b))
+            == 0
+}
+
+/// Whether the client closed its end. It sends nothing after its request,
+/// so any read that does not block means it is gone.
+fn disconnected(stream: &TcpStream) -> io::Result<bool> {
+    stream.set_nonblocking(true)?;
+    let peeked = stream.peek(&mut [0u8; 1]);
+    stream.set_nonblocking(false)?;
+    match peeked {
+        Ok(_) => Ok(true),
+        Err(err) if err.kind() == io::ErrorKind::WouldBlock => Ok(false),
+        Err(err) => Err(err),
+    }
+}
+
+fn send(writer: &mut TcpStream, reply: &Reply) -> io::Result<()> {
+    let mut message = serde_json::to_string(reply)
+        .map_err(|err| io::Error::new(io::ErrorKind::InvalidData, err))?;
+    message.push('\n');
+    writer.write_all(message.as_bytes())
+}
+
+/// Agent ids are UUIDs; anything else could escape the `/chat/` route.
+fn is_agent_id(id: &str) -> bool {
+    !id.is_empty() && id.chars().all(|c| c.is_ascii_alphanumeric() || c == '-')
+}
+
+fn focus_main_window(app: &AppHandle) {
+    let Some(window) = app.get_webview_window("main") else {
+        return;
+    };
+    let _ = window.unminimize();
+    let _ = window.show();
+    if let Err(err) = window.set_focus() {
+        eprintln!("Failed to focus the main window: {}", err);
+    }
+}
+
+/// Connects to the running instance to send it a [`Request`]. Returns
+/// `None` when no instance holds the lock.
+pub fn connect_running(id: &str) -> io::Result<Option<TcpStream>> {
+    let dir = lock_dir();
+    let lock_path = dir.join(format!("{}.lock", id));
+    let port_path = dir.join(format!("{}.port", id));
+    let lock = match OpenOptions::new().write(true).open(&lock_path) {
+        Ok(lock) => lock,
+        Err(err) if err.kind() == io::ErrorKind::NotFound => return Ok(None),
+        Err(err) => return Err(err),
+    };
+    match lock.try_lock() {
+        // Nobody holds it; dropping `lock` releases it again.
+        Ok(()) => Ok(None),
+        Err(TryLockError::WouldBlock) => connect(&port_path).map(Some),
+        Err(TryLockError::Error(err)) => Err(err),
+    }
+}
+
+/// Sends this launch's arguments to the running instance.
+fn forward(port_path: &Path) -> io::Result<()> {
+    let request = Request::Activate {
+        args: env::args().skip(1).collect(),
+        cwd: env::current_dir().unwrap_or_default(),
+    };
+    let mut stream = connect(port_path)?;
+    let mut message = serde_json::to_string(&request)
+        .map_err(|err| io::Error::new(io::ErrorKind::InvalidData, err))?;
+    message.push('\n');
+    stream.write_all(message.as_bytes())
+}
+
+/// Connects to the running instance and presents its secret, waiting
+/// briefly in case it holds the lock but has not published its port yet.
+fn connect(port_path: &Path) -> io::Result<TcpStream> {
+    let deadline = Instant::now() + PORT_WAIT;
+    loop {
+        let endpoint = fs::read_to_string(port_path).ok().and_then(|contents| {
+            let mut lines = contents.lines();
+            let port = lines.next()?.trim().parse::<u16>().ok()?;
+            let secret = lines.next()?.trim().to_string();
+            Some((port, secret))
+        });
+        if let Some((port, secret)) = endpoint {
+            let addr = SocketAddr::from((Ipv4Addr::LOCALHOST, port));
+            match TcpStream::connect_timeout(&addr, CONNECT_TIMEOUT) {
+                Ok(mut stream) => {
+                    stream.write_all(format!("{}\n", secret).as_bytes())?;
+                    return Ok(stream);
+                }
+                Err(err) if Instant::now() >= deadline => return Err(err),
+                Err(_) => {}
+            }
+        } else if Instant::now() >= deadline {
+            return Err(io::Error::new(
+                io::ErrorKind::NotFound,
+                format!("{} does not hold a port", port_path.display()),
+            ));
+        }
+        thread::sleep(PORT_POLL_INTERVAL);
+    }
+}
+
+/// Per-user directory for the lock files: `XDG_RUNTIME_DIR` when set,
+/// otherwise a user-specific directory under the system temp dir. Checked
+/// by [`create_private_dir`] before use.
+fn lock_dir() -> PathBuf {
+    if let Some(dir) = env::var_os("XDG_RUNTIME_DIR").filter(|dir| !dir.is_empty()) {
+        return PathBuf::from(dir);
+    }
+    let user = env::var("USER")
+        .or_else(|_| env::var("USERNAME"))
+        .unwrap_or_else(|_| "default".into());
+    env::temp_dir().join(format!("eliza-desktop-{}", user))
+}
+
+/// Creates `dir` readable only by this user, or checks that an existing one
+/// is. In a shared temp dir another user could have created it first to
+/// plant a port file.
+fn create_private_dir(dir: &Path) -> io::Result<()> {
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::{DirBuilderExt, MetadataExt, PermissionsExt};
+
+        match fs::DirBuilder::new().mode(0o700).create(dir) {
+            Ok(()) => {}
+            Err(err) if err.kind() == io::ErrorKind::AlreadyExists => {}
+            Err(err) => return Err(err),
+        }
+        let metadata = fs::symlink_metadata(dir)?;
+        // SAFETY: geteuid has no preconditions and cannot fail.
+        let uid = unsafe { libc::geteuid() };
+        if !metadata.is_dir() || metadata.uid() != uid {
+            return Err(io::Error::new(
+                io::ErrorKind::PermissionDenied,
+                format!("{} is not a directory owned by this user", dir.display()),
+            ));
+        }
+        if metadata.mode() & 0o077 != 0 {
+            fs::set_permissions(dir, fs::Permissions::from_mode(0o700))?;
+        }
+        Ok(())
+    }
+
+    // On Windows the temp dir is already private to the user.
+    #[cfg(not(unix))]
+    fs::create_dir_all(dir)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn compares_secrets_exactly() {
+        assert!(same_secret("abc123", "abc123"));
+        assert!(!same_secret("abc124", "abc123"));
+        assert!(!same_secret("abc12", "abc123"));
+        assert!(!same_secret("", "abc123"));
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn keeps_the_lock_dir_private() {
+        use std::os::unix::fs::{symlink, PermissionsExt};
+
+        let root = env::temp_dir().join(format!("eliza-instance-test-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&root);
+        fs::create_dir_all(&root).unwrap();
+
+        let dir = root.join("new");
+        create_private_dir(&dir).unwrap();
+        let mode = fs::metadata(&dir).unwrap().permissions().mode();
+        assert_eq!(mode & 0o777, 0o700);
+
+        // An existing dir that others can write to is tightened.
+        let open = root.join("open");
+        fs::create_dir(&open).unwrap();
+        fs::set_permissions(&open, fs::Permissions::from_mode(0o777)).unwrap();
+        create_private_dir(&open).unwrap();
+        let mode = fs::metadata(&open).unwrap().permissions().mode();
+        assert_eq!(mode & 0o777, 0o700);
+
+        // A symlink could point anywhere, e.g. at another user's dir.
+        let link = root.join("link");
+        symlink(&dir, &link).unwrap();
+        assert!(create_private_dir(&link).is_err());
+
+        fs::remove_dir_all(&root).unwrap();
+    }
+}
@@ -0,0 +1,176 @@
+use serde::Serialize;
+use std::env;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+use crate::error::{AppError, ErrorReport};
+
+const CLI_NAME: &str = "elizaos";
+
+/// CLI entry point inside an ElizaOS monorepo checkout.
+const WORKSPACE_ENTRY: &[&str] = &["packages", "cli", "dist", "index.js"];
+
+/// Where the `elizaos` launcher was found, in resolution order.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub enum LauncherSource {
+    /// The path configured by the user.
+    Explicit,
+    /// `packages/cli/dist/index.js` of an enclosing ElizaOS workspace.
+    Workspace,
+    /// `bun x elizaos`.
+    BunX,
+    /// `elizaos` found on `PATH`.
+    Path,
+}
+
+/// A resolved way to run the ElizaOS CLI.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Launcher {
+    pub source: LauncherSource,
+    pub program: PathBuf,
+    /// Arguments placed before the CLI's own arguments.
+    pub args: Vec<String>,
+}
+
+impl Launcher {
+    /// A command running `elizaos` with no arguments yet.
+    pub fn command(&self) -> Command {
+        let mut command = Command::new(&self.program);
+        command.args(&self.args);
+        command
+    }
+
+    /// Whether the CLI is run with bun, either as `bun x elizaos` or as a
+    /// script bun executes.
+    pub fn runs_on_bun(&self) -> bool {
+        self.source == LauncherSource::BunX
+            || self
+                .program
+                .file_stem()
+                .is_some_and(|stem| stem.eq_ignore_ascii_case("bun"))
+    }
+
+    /// The command line, for logs and error messages.
+    pub fn display(&self) -> String {
+        std::iter::once(self.program.display().to_string())
+            .chain(self.args.iter().cloned())
+            .collect::<Vec<_>>()
+            .join(" ")
+    }
+}
+
+/// Resolves the launcher, checking `explicit`, the workspace CLI build,
+/// `bun x elizaos` and `PATH` in that order. A configured `explicit` path
+/// that does not exist is an error rather than a reason to fall back.
+pub fn resolve(explicit: Option<&Path>) -> Result<Launcher, AppError> {
+    if let Some(path) = explicit {
+        if !path.is_file() {
+            return Err(AppError::Config {
+                key: "launcher path".into(),
+                message: format!("{} does not exist", path.display()),
+            });
+        }
+        return Ok(
+            script_or_binary(LauncherSource::Explicit, path).unwrap_or_else(|| Launcher {
+                source: LauncherSource::Explicit,
+                program: path.to_path_buf(),
+                args: Vec::new(),
+            }),
+        );
+    }
+
+    if let Some(launcher) =
+        find_workspace_entry().and_then(|entry| script_or_binary(LauncherSource::Workspace, &entry))
+    {
+        return Ok(launcher);
+    }
+
+    if let Some(bun) = find_on_path("bun") {
+        return Ok(Launcher {
+            source: LauncherSource::BunX,
+            program: bun,
+            args: vec!["x".into(), CLI_NAME.into()],
+        });
+    }
+
+    if let Some(program) = find_on_path(CLI_NAME) {
+        return Ok(Launcher {
+            source: LauncherSource::Path,
+            program,
+            args: Vec::new(),
+        });
+    }
+
+    Err(AppError::BinaryNotFound {
+        binary: CLI_NAME.into(),
+    })
+}
+
+/// Wraps JavaScript entry points in `bun` or `node`. Returns `None` for a
+/// JavaScript file when neither runtime is installed, and runs anything else
+/// directly.
+fn script_or_binary(source: LauncherSource, path: &Path) -> Option<Launcher> {
+    let is_script = path
+        .extension()
+        .is_some_and(|ext| ext == "js" || ext == "mjs");
+    if !is_script {
+        return Some(Launcher {
+            source,
+            program: path.to_path_buf(),
+            args: Vec::new(),
+        });
+    }
+    let runtime = find_on_path("bun").or_else(|| find_on_path("node"))?;
+    Some(Launcher {
+        source,
+        program: runtime,
+        args: vec![path.display().to_string()],
+    })
+}
+
+/// Looks for the workspace CLI build above the executable and the current
+/// directory, which covers `tauri dev` inside the monorepo.
+fn find_workspace_entry() -> Option<PathBuf> {
+    let exe_dir = env::current_exe()
+        .ok()
+        .and_then(|exe| exe.parent().map(Path::to_path_buf));
+    let roots = exe_dir.into_iter().chain(env::current_dir().ok());
+    for root in roots {
+        for dir in root.ancestors() {
+            let entry: PathBuf = WORKSPACE_ENTRY
+                .iter()
+                .fold(dir.to_path_buf(), |p, s| p.join(s));
+            if entry.is_file() {
+                return Some(entry);
+            }
+        }
+    }
+    None
+}
+
+pub fn find_on_path(name: &str) -> Option<PathBuf> {
+    let paths = env::var_os("PATH")?;
+    let extensions: Vec<String> = if cfg!(windows) {
+        env::var("PATHEXT")
+            .unwrap_or_else(|_| ".EXE;.CMD;.BAT".into())
+            .split(';')
+            .map(|ext| ext.to_ascii_lowercase())
+            .chain(std::iter::once(String::new()))
+            .collect()
+    } else {
+        vec![String::new()]
+    };
+    env::split_paths(&paths).find_map(|dir| {
+        extensions
+            .iter()
+            .map(|ext| dir.join(format!("{}{}", name, ext)))
+            .find(|candidate| candidate.is_file())
+    })
+}
+
+#[tauri::command]
+pub fn get_launcher() -> Result<Launcher, ErrorReport> {
+    resolve(crate::config::current().launcher.as_deref()).map_err(|err| err.report())
+}
@@ -1,47 +1,488 @@
-use std::net::TcpStream;
-use std::process::{Child, Command};
+mod auth;
+mod cli;
+mod config;
+mod csp;
+mod diagnostics;
+mod error;
+mod headless;
+mod health;
+mod host;
+mod instance;
+mod launcher;
+mod log_files;
+mod logs;
+mod paths;
+mod pid_file;
+mod pino;
+mod preflight;
+mod process;
+mod proxy;
+mod settings;
+mod signals;
+mod state;
+mod supervisor;
+mod vault;
+
+use std::process::{Child, Stdio};
 use std::sync::{Arc, Mutex};
-use tauri::Manager;
+use tauri::{AppHandle, Manager};
+
+use crate::error::AppError;
+use crate::host::Host;
 
-static SERVER_PROCESS: once_cell::sync::Lazy<Arc<Mutex<Option<Child>>>> = 
+static SERVER_PROCESS: once_cell::sync::Lazy<Arc<Mutex<Option<Child>>>> =
     once_cell::sync::Lazy::new(|| Arc::new(Mutex::new(None)));
 
-fn is_server_running() -> bool {
-    TcpStream::connect("127.0.0.1:3000").is_ok()
+/// Spawns `elizaos start`, captures its output and stores the child in
+/// `SERVER_PROCESS`.
+fn spawn_server(host: &Host) -> Result<u32, AppError> {
+    println!("Starting Eliza server...");
+    let config = config::current();
+    let launcher = launcher::resolve(config.launcher.as_deref())?;
+    println!(
+        "Using {:?} launcher: {}",
+        launcher.source,
+        launcher.display()
+    );
+    let mut command = launcher.command();
+    command
+        .arg("start")
+        .arg("--port")
+        .arg(config.port.to_string())
+        .env(config::PORT_ENV, config.port.to_string())
+        .env(config::HOST_ENV, &config.host)
+        // Structured output lets the log pipeline keep level, agent and src.
+        .env("LOG_JSON_FORMAT", "true")
+        .stdin(Stdio::null())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped());
+    if let Some(token) = &config.auth_token {
+        command.env(config::AUTH_TOKEN_ENV, token);
+    }
+    let secrets = vault::server_env();
+    if !secrets.is_empty() {
+        println!(
+            "Passing {} secret(s) from the vault to the server",
+            secrets.len()
+        );
+    }
+    for (name, value) in &secrets {
+        command.env(name, value.as_str());
+    }
+    command.env_remove(vault::PASSPHRASE_ENV);
+    process::isolate(&mut command);
+    let mut child = command
+        .spawn()
+        .map_err(|err| AppError::spawn(&launcher.display(), err))?;
+    let pid = child.id();
+    log_files::start_launch(pid);
+    pid_file::record(
+        pid,
+        format!("{} start --port {}", launcher.display(), config.port),
+        config.port,
+    );
+    logs::capture(host, &mut child);
+    let mut server_guard = SERVER_PROCESS
+        .lock()
+        .expect("SERVER_PROCESS mutex should not be poisoned");
+    *server_guard = Some(child);
+    println!("Eliza server process started (pid {})", pid);
+    Ok(pid)
 }
 
-fn shutdown_server() {
+/// Stops the server with SIGTERM, escalating to SIGKILL once the grace
+/// period from `process::shutdown_grace()` runs out.
+fn shutdown_server() -> process::ShutdownOutcome {
+    supervisor::request_stop();
+    let child = SERVER_PROCESS
+        .lock()
+        .expect("SERVER_PROCESS mutex should not be poisoned")
+        .take();
+    let Some(mut child) = child else {
+        return process::ShutdownOutcome::NotRunning;
+    };
+
     println!("Shutting down Eliza server...");
-    let mut guard = SERVER_PROCESS.lock().expect("SERVER_PROCESS mutex should not be poisoned");
-    if let Some(ref mut child) = *guard {
-        child.kill().expect("Failed to kill Eliza server process");
-        println!("Eliza server shut down successfully");
+    let outcome = process::terminate(&mut child, process::shutdown_grace());
+    match &outcome {
+        process::ShutdownOutcome::Failed { message } => {
+            eprintln!("Failed to shut down Eliza server: {}", message)
+        }
+        outcome => {
+            println!("Eliza server shut down: {:?}", outcome);
+            pid_file::clear(child.id());
+        }
+    }
+    outcome
+}
+
+/// Resolves the server config from the settings and environment and probes
+/// its port, or checks the remote server in remote mode. When
+/// `allow_port_change` is set and auto-port is enabled, a port held by
+/// another process is swapped for a free one; this only happens at launch,
+/// before anything has used the port.
+fn resolve_server(allow_port_change: bool) -> Result<health::ProbeResult, AppError> {
+    let mut config = settings::server_config()?;
+    // A remote server has its own token, which can only come from the
+    // environment; a local one uses this install's unless it sets one.
+    if config.auth_token.is_none() && !config.is_remote() {
+        config.auth_token = auth::token();
+    }
+    config::set(config.clone());
+    if config.is_remote() {
+        return connect_remote(&config.api_url());
+    }
+
+    match health::probe(&config.api_url()) {
+        health::ProbeResult::Foreign { reason } if allow_port_change && config.auto_port => {
+            let port = config
+                .select_free_port()
+                .map_err(|err| AppError::PortConflict {
+                    port: config.port,
+                    reason: format!("{}; no free port available: {}", reason, err),
+                })?;
+            println!(
+                "Port is held by another process ({}), using port {}",
+                reason, port
+            );
+            config::set(config);
+            Ok(health::ProbeResult::NotRunning)
+        }
+        health::ProbeResult::Foreign { reason } => Err(AppError::PortConflict {
+            port: config.port,
+            reason,
+        }),
+        health::ProbeResult::Unauthorized => Err(AppError::Unauthorized {
+            url: config.api_url(),
+        }),
+        probe => Ok(probe),
+    }
+}
+
+/// Checks that `url` is an Eliza server that accepts our API key, through
+/// its health and version routes. A server that is still starting passes;
+/// the monitor waits for it.
+fn connect_remote(url: &str) -> Result<health::ProbeResult, AppError> {
+    let unavailable = |reason: String| AppError::RemoteUnavailable {
+        url: url.to_string(),
+        reason,
+    };
+    match health::probe(url) {
+        probe @ health::ProbeResult::Healthy { .. } => {
+            let version = health::system_version(url).map_err(unavailable)?;
+            println!(
+                "Connected to remote Eliza server {} ({}) at {}",
+                version.version, version.environment, url
+            );
+            Ok(probe)
+        }
+        health::ProbeResult::Starting => Ok(health::ProbeResult::Starting),
+        health::ProbeResult::NotRunning => Err(unavailable("the server is not reachable".into())),
+        health::ProbeResult::Unauthorized => Err(AppError::Unauthorized {
+            url: url.to_string(),
+        }),
+        health::ProbeResult::Foreign { reason } => Err(unavailable(reason)),
+    }
+}
+
+/// Attaches to an Eliza server that is already listening or starts the
+/// supervisor to spawn one. In remote mode the server is only monitored.
+fn start_or_attach(host: &Host, startup: Result<health::ProbeResult, AppError>) {
+    match startup {
+        Ok(probe) if config::current().is_remote() => supervisor::monitor(host.clone(), probe),
+        Ok(health::ProbeResult::NotRunning) => {
+            supervisor::start(host.clone(), supervisor::RestartPolicy::default());
+        }
+        Ok(health::ProbeResult::Healthy {
+            version,
+            agents_ready,
+        }) => {
+            println!("Eliza server is already running");
+            state::set(
+                host,
+                state::ServerState::Ready {
+                    version,
+                    agents_ready,
+                    external: true,
+                },
+            );
+        }
+        Ok(health::ProbeResult::Starting) => {
+            println!("Eliza server is already starting");
+            supervisor::await_external(host.clone(), supervisor::RestartPolicy::default());
+        }
+        Ok(health::ProbeResult::Unauthorized) => {
+            start_or_attach(
+                host,
+                Err(AppError::Unauthorized {
+                    url: config::current().api_url(),
+                }),
+            );
+        }
+        Ok(health::ProbeResult::Foreign { reason }) => {
+            start_or_attach(
+                host,
+                Err(AppError::PortConflict {
+                    port: config::current().port,
+                    reason,
+                }),
+            );
+        }
+        Err(err) => {
+            eprintln!("{}", err);
+            state::set(
+                host,
+                state::ServerState::Crashed {
+                    error: err.report(),
+                    will_restart: false,
+                },
+            );
+        }
     }
-    *guard = None;
+}
+
+/// Runs startup again after it failed or the server stopped. Does nothing
+/// while the server is starting or running.
+#[tauri::command]
+async fn retry_startup(app: AppHandle) -> Result<state::ServerState, String> {
+    tauri::async_runtime::spawn_blocking(move || {
+        let host = Host::from(app);
+        let _control = supervisor::control();
+        match state::current() {
+            state::ServerState::Idle
+            | state::ServerState::Stopped
+            | state::ServerState::Crashed {
+                will_restart: false,
+                ..
+            } => {
+                state::set(&host, state::ServerState::Idle);
+                start_or_attach(&host, resolve_server(false));
+            }
+            _ => {}
+        }
+        state::current()
+    })
+    .await
+    .map_err(|err| err.to_string())
+}
+
+/// Stops the server an earlier session left running. If the app had
+/// attached to it, startup runs again so the app spawns its own server.
+#[tauri::command]
+async fn reap_orphan_server(app: AppHandle) -> Result<state::ServerState, String> {
+    tauri::async_runtime::spawn_blocking(move || {
+        let host = Host::from(app);
+        let orphan = pid_file::take_orphan()
+            .ok_or_else(|| "No server from an earlier session is running".to_string())?;
+        println!(
+            "Stopping Eliza server (pid {}) left running by an earlier session",
+            orphan.pid
+        );
+        match process::terminate_group(orphan.pid, process::shutdown_grace()) {
+            process::ShutdownOutcome::Failed { message } => {
+                return Err(format!("Failed to stop the old Eliza server: {}", message))
+            }
+            outcome => println!("Old Eliza server shut down: {:?}", outcome),
+        }
+        pid_file::clear(orphan.pid);
+
+        let _control = supervisor::control();
+        if let state::ServerState::Ready { external: true, .. } = state::current() {
+            state::set(&host, state::ServerState::Idle);
+            start_or_attach(&host, resolve_server(false));
+        }
+        Ok(state::current())
+    })
+    .await
+    .map_err(|err| err.to_string())?
+}
+
+/// Starts the server unless it is already starting or running.
+#[tauri::command]
+async fn start_server(app: AppHandle) -> Result<state::ServerState, String> {
+    tauri::async_runtime::spawn_blocking(move || {
+        let host = Host::from(app);
+        let _control = supervisor::control();
+        if !supervisor::is_active() && !matches!(state::current(), state::ServerState::Ready { .. })
+        {
+            state::set(&host, state::ServerState::Idle);
+            start_or_attach(&host, resolve_server(false));
+        }
+        state::current()
+    })
+    .await
+    .map_err(|err| err.to_string())
+}
+
+/// Stops the server the app spawned. A server the app only attached to is
+/// left running.
+#[tauri::command]
+async fn stop_server(app: AppHandle) -> Result<state::ServerState, String> {
+    tauri::async_runtime::spawn_blocking(move || {
+        let host = Host::from(app);
+        let _control = supervisor::control();
+        stop_supervised(&host)?;
+        Ok(state::current())
+    })
+    .await
+    .map_err(|err| err.to_string())?
+}
+
+/// Stops the server and starts it again, waiting for the old one to exit
+/// before the new one is spawned.
+#[tauri::command]
+async fn restart_server(app: AppHandle) -> Result<state::ServerState, String> {
+    tauri::async_runtime::spawn_blocking(move || {
+        let host = Host::from(app);
+        let _control = supervisor::control();
+        stop_supervised(&host)?;
+        state::set(&host, state::ServerState::Idle);
+        start_or_attach(&host, resolve_server(false));
+        Ok(state::current())
+    })
+    .await
+    .map_err(|err| err.to_string())?
+}
+
+/// Shared by [`stop_server`], [`restart_server`] and the command-line `stop`;
+/// callers hold `supervisor::control()`.
+fn stop_supervised(host: &Host) -> Result<(), String> {
+    if let state::ServerState::Ready { external: true, .. } = state::current() {
+        return Err("The Eliza server was not started by this app".into());
+    }
+    if let process::ShutdownOutcome::Failed { message } = supervisor::stop() {
+        return Err(format!("Failed to stop the Eliza server: {}", message));
+    }
+    state::set(host, state::ServerState::Stopped);
+    Ok(())
 }
 
 #[cfg_attr(mobile, tauri::mobile_entry_point)]
 pub fn run() {
+    let mut context = tauri::generate_context!();
+
+    if let Some(command) = cli::parse(std::env::args().skip(1)) {
+        std::process::exit(cli::run(&context.config().identifier, command));
+    }
+    settings::init(
+        paths::app_config_dir(&context.config().identifier)
+            .map(|dir| dir.join(settings::FILE_NAME)),
+    );
+    match paths::app_data_dir(&context.config().identifier) {
+        Some(dir) => {
+            auth::init(&dir.join(auth::FILE_NAME));
+            vault::init(dir.join(vault::FILE_NAME));
+        }
+        None => eprintln!(
+            "The server API will not require a token and secrets cannot be stored: no data directory"
+        ),
+    }
+    if headless::requested() {
+        std::process::exit(headless::run(&context.config().identifier));
+    }
+
+    // A second launch hands over to the running instance before it looks at
+    // the server at all.
+    let instance = match instance::acquire(&context.config().identifier) {
+        Ok(instance::Instance::Primary(guard)) => Some(guard),
+        Ok(instance::Instance::Forwarded) => {
+            println!("Eliza Desktop is already running; switching to it");
+            return;
+        }
+        Err(err) => {
+            eprintln!("Failed to check for a running instance: {}", err);
+            None
+        }
+    };
+
+    // Anything an earlier session left on the port has to be found before
+    // the port is probed.
+    match paths::app_data_dir(&context.config().identifier) {
+        Some(dir) => pid_file::init(dir.join("server.pid")),
+        None => eprintln!("Server PID file will not be written: no data directory"),
+    }
+    pid_file::detect_orphan();
+
+    let settings = settings::current();
+    let startup = resolve_server(true);
+    // The client is served from the proxy, not by Tauri, so its CSP is
+    // built here for the proxy's origin.
+    if let Err(err) = proxy::start(context.config().app.security.csp.clone()) {
+        eprintln!("Failed to start the client proxy: {}", err);
+    }
+    if let Some(window) = context.config_mut().app.windows.first_mut() {
+        window.width = settings.window.width.into();
+        window.height = settings.window.height.into();
+        window.maximized = settings.window.maximized;
+    }
+
     let app = tauri::Builder::default()
         .plugin(tauri_plugin_opener::init())
         .plugin(tauri_plugin_shell::init())
-        .setup(|app| {
-            if !is_server_running() {
-                println!("Starting Eliza server...");
-                let child = Command::new("elizaos")
-                    .arg("start")
-                    .spawn()
-                    .expect("Failed to start Eliza server");
-                let mut server_guard = SERVER_PROCESS.lock().expect("SERVER_PROCESS mutex should not be poisoned");
-                *server_guard = Some(child);
-                println!("Eliza server process started");
+        .invoke_handler(tauri::generate_handler![
+            state::get_server_state,
+            config::get_server_url,
+            proxy::get_client_url,
+            launcher::get_launcher,
+            logs::get_server_logs,
+            log_files::open_server_log,
+            pid_file::get_orphan_server,
+            preflight::run_preflight,
+            diagnostics::export_diagnostics,
+            settings::get_settings,
+            settings::update_settings,
+            vault::get_vault_status,
+            vault::unlock_vault,
+            vault::create_vault,
+            vault::lock_vault,
+            vault::list_secrets,
+            vault::set_secret,
+            vault::delete_secret,
+            reap_orphan_server,
+            retry_startup,
+            start_server,
+            stop_server,
+            restart_server
+        ])
+        .setup(move |app| {
+            match app.path().app_log_dir() {
+                Ok(dir) => log_files::init(dir.join("server"), settings.retention_policy()),
+                Err(err) => eprintln!("Server logs will not be written to disk: {}", err),
+            }
+            if let Some(guard) = instance {
+                instance::listen(Host::from(app.handle().clone()), guard);
+            }
+            if settings.auto_start || !matches!(startup, Ok(health::ProbeResult::NotRunning)) {
+                start_or_attach(&Host::from(app.handle().clone()), startup);
             } else {
-                println!("Eliza server is already running");
+                println!("Auto-start is off; the Eliza server waits to be started");
+            }
+
+            // Ctrl-C in `tauri dev` or a `kill` of the app goes through the
+            // same shutdown as closing the window.
+            let handle = app.handle().clone();
+            let watched = signals::watch(move |signal| {
+                println!(
+                    "Received signal {}, shutting down; a second signal exits at once",
+                    signal
+                );
+                {
+                    let _control = supervisor::control();
+                    supervisor::stop();
+                }
+                handle.exit(128 + signal);
+            });
+            if let Err(err) = watched {
+                eprintln!("Failed to install signal handlers: {}", err);
             }
-            
+
             #[cfg(desktop)]
             {
                 if let Some(main_window) = app.get_webview_window("main") {
+                    match main_window.url() {
+                        Ok(url) => proxy::remember_app_url(url),
+                        Err(err) => eprintln!("Failed to read the main window URL: {}", err),
+                    }
                     main_window.on_window_event(move |event| {
                         if let tauri::WindowEvent::CloseRequested { .. } = event {
                             shutdown_server();
@@ -49,12 +490,12 @@ pub fn run() {
                     });
                 }
             }
-            
+
             Ok(())
         })
-        .build(tauri::generate_context!())
+        .build(context)
         .expect("Failed to build Tauri application");
-        
+
     app.run(|_, event| {
         if let tauri::RunEvent::Exit = event {
             shutdown_server();
@@ -0,0 +1,408 @@
+use std::cmp::Reverse;
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, BufWriter, Write};
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use tauri::AppHandle;
+use tauri_plugin_opener::OpenerExt;
+
+use crate::logs::{LogLine, LogStream};
+
+const FILE_PREFIX: &str = "server-";
+const FILE_SUFFIX: &str = ".log";
+
+static LOG_FILES: once_cell::sync::Lazy<Mutex<Option<LogFiles>>> =
+    once_cell::sync::Lazy::new(|| Mutex::new(None));
+
+/// How much server output is kept on disk.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RetentionPolicy {
+    /// A launch's log moves on to a new part once its file reaches this size.
+    pub max_file_bytes: u64,
+    /// Files older than this are deleted.
+    pub max_age: Duration,
+    /// At most this many files are kept, newest first.
+    pub max_files: usize,
+}
+
+impl Default for RetentionPolicy {
+    fn default() -> Self {
+        Self {
+            max_file_bytes: 10 * 1024 * 1024,
+            max_age: Duration::from_secs(14 * 24 * 60 * 60),
+            max_files: 20,
+        }
+    }
+}
+
+struct LogFiles {
+    dir: PathBuf,
+    policy: RetentionPolicy,
+    current: Option<CurrentFile>,
+}
+
+struct CurrentFile {
+    /// `server-<timestamp>` of the launch, shared by all its parts.
+    stem: String,
+    part: u32,
+    path: PathBuf,
+    writer: BufWriter<File>,
+    written: u64,
+}
+
+impl LogFiles {
+    fn start_launch(&mut self, pid: u32) -> io::Result<()> {
+        fs::create_dir_all(&self.dir)?;
+        let stem = format!("{}{}", FILE_PREFIX, file_timestamp(SystemTime::now()));
+        self.open(stem, 0)?;
+        let header = format!("# Eliza server pid {}", pid);
+        self.write_raw(&header)?;
+        prune(&self.dir, &self.policy, self.current_path())
+    }
+
+    fn open(&mut self, stem: String, part: u32) -> io::Result<()> {
+        let name = if part == 0 {
+            format!("{}{}", stem, FILE_SUFFIX)
+        } else {
+            format!("{}.{}{}", stem, part, FILE_SUFFIX)
+        };
+        let path = self.dir.join(name);
+        let file = OpenOptions::new().create(true).append(true).open(&path)?;
+        let written = file.metadata()?.len();
+        self.current = Some(CurrentFile {
+            stem,
+            part,
+            path,
+            writer: BufWriter::new(file),
+            written,
+        });
+        Ok(())
+    }
+
+    fn write_line(&mut self, line: &LogLine) -> io::Result<()> {
+        let stream = match line.stream {
+            LogStream::Stdout => "out",
+            LogStream::Stderr => "err",
+        };
+        let text = format!("{} {} {}", line.time, stream, line.text);
+        self.write_raw(&text)
+    }
+
+    fn write_raw(&mut self, text: &str) -> io::Result<()> {
+        let Some(current) = self.current.as_mut() else {
+            return Ok(());
+        };
+        if current.written >= self.policy.max_file_bytes {
+            current.writer.flush()?;
+            let (stem, part) = (current.stem.clone(), current.part + 1);
+            self.open(stem, part)?;
+            prune(&self.dir, &self.policy, self.current_path())?;
+        }
+        let current = self.current.as_mut().expect("log file was just opened");
+        writeln!(current.writer, "{}", text)?;
+        // Flush per line so the file is complete if the app is killed.
+        current.writer.flush()?;
+        current.written += text.len() as u64 + 1;
+        Ok(())
+    }
+
+    fn current_path(&self) -> Option<&Path> {
+        self.current.as_ref().map(|current| current.path.as_path())
+    }
+}
+
+/// Sets the directory server logs are written to. Nothing is written until
+/// the first launch.
+pub fn init(dir: PathBuf, policy: RetentionPolicy) {
+    *LOG_FILES
+        .lock()
+        .expect("LOG_FILES mutex should not be poisoned") = Some(LogFiles {
+        dir,
+        policy,
+        current: None,
+    });
+}
+
+/// Replaces the retention policy; it applies from the next rotation or
+/// launch on.
+pub fn set_policy(policy: RetentionPolicy) {
+    with_files(|files| {
+        files.policy = policy;
+        Ok(())
+    });
+}
+
+/// Starts a new log file for a freshly spawned server and prunes old ones.
+pub fn start_launch(pid: u32) {
+    with_files(|files| files.start_launch(pid));
+}
+
+pub fn write(line: &LogLine) {
+    with_files(|files| files.write_line(line));
+}
+
+/// The file the current launch is writing to, if any.
+pub fn current_file() -> Option<PathBuf> {
+    LOG_FILES
+        .lock()
+        .expect("LOG_FILES mutex should not be poisoned")
+        .as_ref()
+        .and_then(|files| files.current_path().map(Path::to_path_buf))
+}
+
+pub fn log_dir() -> Option<PathBuf> {
+    LOG_FILES
+        .lock()
+        .expect("LOG_FILES mutex should not be poisoned")
+        .as_ref()
+        .map(|files| files.dir.clone())
+}
+
+/// Up to `count` server log files, newest first.
+pub fn recent_files(count: usize) -> Vec<PathBuf> {
+    let Some(dir) = log_dir() else {
+        return Vec::new();
+    };
+    let Ok(entries) = fs::read_dir(&dir) else {
+        return Vec::new();
+    };
+    let mut files: Vec<(SystemTime, PathBuf)> = entries
+        .filter_map(Result::ok)
+        .filter(|entry| is_log_file(&entry.file_name().to_string_lossy()))
+        .filter_map(|entry| Some((entry.metadata().ok()?.modified().ok()?, entry.path())))
+        .collect();
+    files.sort_by_key(|(modified, _)| Reverse(*modified));
+    files
+        .into_iter()
+        .take(count)
+        .map(|(_, path)| path)
+        .collect()
+}
+
+fn is_log_file(name: &str) -> bool {
+    name.starts_with(FILE_PREFIX) && name.ends_with(FILE_SUFFIX)
+}
+
+fn with_files(f: impl FnOnce(&mut LogFiles) -> io::Result<()>) {
+    let mut guard = LOG_FILES
+        .lock()
+        .expect("LOG_FILES mutex should not be poisoned");
+    if let Some(files) = guard.as_mut() {
+        if let Err(err) = f(files) {
+            eprintln!("Failed to write server log file: {}", err);
+        }
+    }
+}
+
+/// Deletes server log files older than the policy allows, then the oldest
+/// ones beyond `max_files`. `keep` is never deleted.
+fn prune(dir: &Path, policy: &RetentionPolicy, keep: Option<&Path>) -> io::Result<()> {
+    let mut files: Vec<(SystemTime, PathBuf)> = fs::read_dir(dir)?
+        .filter_map(Result::ok)
+        .filter(|entry| is_log_file(&entry.file_name().to_string_lossy()))
+        .filter_map(|entry| {
+            let modified = entry.metadata().ok()?.modified().ok()?;
+            Some((modified, entry.path()))
+        })
+        .filter(|(_, path)| Some(path.as_path()) != keep)
+        .collect();
+    files.sort_by_key(|(modified, _)| Reverse(*modified));
+
+    let now = SystemTime::now();
+    let keep_count = policy.max_files.saturating_sub(usize::from(keep.is_some()));
+    for (index, (modified, path)) in files.iter().enumerate() {
+        let expired = now
+            .duration_since(*modified)
+            .is_ok_and(|age| age > policy.max_age);
+        if expired || index >= keep_count {
+            if let Err(err) = fs::remove_file(path) {
+                eprintln!("Failed to remove old log file {}: {}", path.display(), err);
+            }
+        }
+    }
+    Ok(())
+}
+
+/// `YYYYMMDD-HHMMSS` in UTC, so file names sort chronologically.
+pub fn file_timestamp(time: SystemTime) -> String {
+    let secs = time
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or(0);
+    let (days, rem) = (secs / 86_400, secs % 86_400);
+    let (year, month, day) = civil_from_days(days as i64);
+    format!(
+        "{:04}{:02}{:02}-{:02}{:02}{:02}",
+        year,
+        month,
+        day,
+        rem / 3600,
+        rem % 3600 / 60,
+        rem % 60
+    )
+}
+
+/// Converts days since 1970-01-01 to a (year, month, day) civil date.
+fn civil_from_days(days: i64) -> (i64, u32, u32) {
+    let z = days + 719_468;
+    let era = z.div_euclid(146_097);
+    let doe = z.rem_euclid(146_097);
+    let yoe = (doe - doe / 1460 + doe / 36_524 - doe / 146_096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = (doy - (153 * mp + 2) / 5 + 1) as u32;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 } as u32;
+    let year = yoe + era * 400 + i64::from(month <= 2);
+    (year, month, day)
+}
+
+/// Opens the current server log file, or the log directory when no server
+/// has been launched yet.
+#[tauri::command]
+pub fn open_server_log(app: AppHandle) -> Result<(), String> {
+    let path = current_file()
+        .or_else(log_dir)
+        .ok_or_else(|| "Server logs are not being written".to_string())?;
+    app.opener()
+        .open_path(path.display().to_string(), None::<&str>)
+        .map_err(|err| err.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const DAY: Duration = Duration::from_secs(24 * 60 * 60);
+
+    fn temp_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!(
+            "eliza-log-files-test-{}-{}",
+            name,
+            std::process::id()
+        ));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    /// Creates a log file last modified `age` ago.
+    fn aged_file(dir: &Path, name: &str, age: Duration) -> PathBuf {
+        let path = dir.join(name);
+        let file = File::create(&path).unwrap();
+        file.set_modified(SystemTime::now() - age).unwrap();
+        path
+    }
+
+    fn names(dir: &Path) -> Vec<String> {
+        let mut names: Vec<String> = fs::read_dir(dir)
+            .unwrap()
+            .map(|entry| entry.unwrap().file_name().to_string_lossy().into_owned())
+            .collect();
+        names.sort();
+        names
+    }
+
+    #[test]
+    fn moves_on_to_a_new_part_once_a_file_is_full() {
+        let dir = temp_dir("rotate");
+        let mut files = LogFiles {
+            dir: dir.clone(),
+            policy: RetentionPolicy {
+                max_file_bytes: 20,
+                ..RetentionPolicy::default()
+            },
+            current: None,
+        };
+        // Nothing is written before a launch opens a file.
+        files.write_raw("dropped").unwrap();
+        assert!(names(&dir).is_empty());
+
+        files.open("server-x".into(), 0).unwrap();
+        for line in [
+            "line 1 ...",
+            "line 2 ...",
+            "line 3 ...",
+            "line 4 ...",
+            "line 5",
+        ] {
+            files.write_raw(line).unwrap();
+        }
+        assert_eq!(
+            names(&dir),
+            ["server-x.1.log", "server-x.2.log", "server-x.log"]
+        );
+        let read = |name: &str| fs::read_to_string(dir.join(name)).unwrap();
+        assert_eq!(read("server-x.log"), "line 1 ...\nline 2 ...\n");
+        assert_eq!(read("server-x.1.log"), "line 3 ...\nline 4 ...\n");
+        assert_eq!(read("server-x.2.log"), "line 5\n");
+        assert_eq!(
+            files.current_path(),
+            Some(dir.join("server-x.2.log").as_path())
+        );
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn prunes_files_older_than_the_policy_allows() {
+        let dir = temp_dir("age");
+        aged_file(&dir, "server-new.log", DAY);
+        aged_file(&dir, "server-old.log", 15 * DAY);
+        let current = aged_file(&dir, "server-current.log", 30 * DAY);
+        // Anything that is not a server log is left alone.
+        aged_file(&dir, "notes.txt", 30 * DAY);
+
+        prune(&dir, &RetentionPolicy::default(), Some(&current)).unwrap();
+        assert_eq!(
+            names(&dir),
+            ["notes.txt", "server-current.log", "server-new.log"]
+        );
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn keeps_at_most_max_files_counting_the_current_one() {
+        let policy = RetentionPolicy {
+            max_files: 3,
+            ..RetentionPolicy::default()
+        };
+        let create = |dir: &Path| {
+            for (index, name) in ["a", "b", "c", "d", "e"].iter().enumerate() {
+                let age = Duration::from_secs(60 * (index as u64 + 1));
+                aged_file(dir, &format!("server-{}.log", name), age);
+            }
+        };
+
+        let dir = temp_dir("count");
+        create(&dir);
+        prune(&dir, &policy, None).unwrap();
+        assert_eq!(
+            names(&dir),
+            ["server-a.log", "server-b.log", "server-c.log"]
+        );
+
+        // The current file takes one of the places, even though it is the
+        // oldest.
+        fs::remove_dir_all(&dir).unwrap();
+        fs::create_dir_all(&dir).unwrap();
+        create(&dir);
+        prune(&dir, &policy, Some(&dir.join("server-e.log"))).unwrap();
+        assert_eq!(
+            names(&dir),
+            ["server-a.log", "server-b.log", "server-e.log"]
+        );
+
+        // A policy of one file leaves only the current one.
+        prune(
+            &dir,
+            &RetentionPolicy {
+                max_files: 1,
+                ..policy
+            },
+            Some(&dir.join("server-e.log")),
+        )
+        .unwrap();
+        assert_eq!(names(&dir), ["server-e.log"]);
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,384 @@
+use serde::{Deserialize, Serialize};
+use std::collections::VecDeque;
+use std::io::{self, BufRead, BufReader, Read, Write};
+use std::process::Child;
+use std::sync::Mutex;
+use std::thread;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use crate::host::Host;
+
+/// Event name carrying each new [`LogLine`].
+pub const LOG_EVENT: &str = "server-log";
+
+/// Number of lines kept in memory; older lines are dropped first.
+const CAPACITY: usize = 5_000;
+
+static LOG_BUFFER: once_cell::sync::Lazy<Mutex<LogBuffer>> =
+    once_cell::sync::Lazy::new(|| Mutex::new(LogBuffer::new(CAPACITY)));
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum LogLevel {
+    Trace,
+    Debug,
+    Info,
+    Warn,
+    Error,
+    Fatal,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub enum LogStream {
+    Stdout,
+    Stderr,
+}
+
+/// One line of server output.
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct LogLine {
+    /// Increases by one per line for the lifetime of the app.
+    pub seq: u64,
+    /// Unix time in milliseconds, taken from the log record when it has one
+    /// and otherwise from when the line was read.
+    pub time: u64,
+    pub stream: LogStream,
+    pub level: LogLevel,
+    /// The line exactly as the server printed it.
+    pub text: String,
+    /// The log message; the whole line for plain text output.
+    pub message: String,
+    /// Subsystem of a structured log record, e.g. `http`.
+    pub src: Option<String>,
+    /// Agent a structured log record belongs to.
+    pub agent_id: Option<String>,
+}
+
+/// Narrows buffered lines; unset fields match everything.
+#[derive(Debug, Clone, Default)]
+struct LogFilter {
+    /// Only lines after this sequence number (exclusive).
+    since: Option<u64>,
+    /// Only lines at this level or above.
+    level: Option<LogLevel>,
+    agent_id: Option<String>,
+    src: Option<String>,
+}
+
+impl LogFilter {
+    fn matches(&self, line: &LogLine) -> bool {
+        self.since.is_none_or(|since| line.seq > since)
+            && self.level.is_none_or(|level| line.level >= level)
+            && self
+                .agent_id
+                .as_ref()
+                .is_none_or(|id| line.agent_id.as_ref() == Some(id))
+            && self
+                .src
+                .as_ref()
+                .is_none_or(|src| line.src.as_ref() == Some(src))
+    }
+}
+
+struct LogBuffer {
+    lines: VecDeque<LogLine>,
+    capacity: usize,
+    next_seq: u64,
+}
+
+impl LogBuffer {
+    fn new(capacity: usize) -> Self {
+        Self {
+            lines: VecDeque::with_capacity(capacity),
+            capacity,
+            next_seq: 0,
+        }
+    }
+
+    fn push(&mut self, stream: LogStream, text: String) -> LogLine {
+        let line = match crate::pino::parse(&text) {
+            Some(record) => LogLine {
+                seq: self.next_seq,
+                time: record.time.unwrap_or_else(now_millis),
+                stream,
+                level: record.level,
+                message: record.message,
+                src: record.src,
+                agent_id: record.agent_id,
+                text,
+            },
+            None => LogLine {
+                seq: self.next_seq,
+                time: now_millis(),
+                stream,
+                level: guess_level(stream, &text),
+                message: strip_ansi(&text),
+                src: None,
+                agent_id: None,
+                text,
+            },
+        };
+        self.next_seq += 1;
+        if self.lines.len() == self.capacity {
+            self.lines.pop_front();
+        }
+        self.lines.push_back(line.clone());
+        line
+    }
+
+    fn query(&self, filter: &LogFilter) -> Vec<LogLine> {
+        self.lines
+            .iter()
+            .filter(|line| filter.matches(line))
+            .cloned()
+            .collect()
+    }
+}
+
+/// Takes the piped stdout and stderr of `child` and forwards every line to
+/// the ring buffer, the log file, the webview and the app's own output.
+pub fn capture(host: &Host, child: &mut Child) {
+    if let Some(stdout) = child.stdout.take() {
+        forward(host.clone(), stdout, LogStream::Stdout);
+    }
+    if let Some(stderr) = child.stderr.take() {
+        forward(host.clone(), stderr, LogStream::Stderr);
+    }
+}
+
+fn forward(host: Host, pipe: impl Read + Send + 'static, stream: LogStream) {
+    let name = match stream {
+        LogStream::Stdout => "eliza-stdout",
+        LogStream::Stderr => "eliza-stderr",
+    };
+    let spawned = thread::Builder::new().name(name.into()).spawn(move || {
+        let mut reader = BufReader::new(pipe);
+        let mut buf = Vec::new();
+        loop {
+            buf.clear();
+            match reader.read_until(b'\n', &mut buf) {
+                Ok(0) => break,
+                Ok(_) => {}
+                Err(err) => {
+                    eprintln!("Failed to read Eliza server {:?}: {}", stream, err);
+                    break;
+                }
+            }
+            let text = String::from_utf8_lossy(&buf)
+                .trim_end_matches(['\r', '\n'])
+                .to_string();
+            echo(stream, &text);
+            let line = LOG_BUFFER
+                .lock()
+                .expect("LOG_BUFFER mutex should not be poisoned")
+                .push(stream, text);
+            crate::log_files::write(&line);
+            if let Err(err) = host.emit(LOG_EVENT, &line) {
+                eprintln!("Failed to emit {} event: {}", LOG_EVENT, err);
+            }
+        }
+    });
+    if let Err(err) = spawned {
+        eprintln!("Failed to spawn {} thread: {}", name, err);
+    }
+}
+
+/// Keeps server output visible when the app runs from a terminal.
+fn echo(stream: LogStream, text: &str) {
+    let _ = match stream {
+        LogStream::Stdout => writeln!(io::stdout().lock(), "{}", text),
+        LogStream::Stderr => writeln!(io::stderr().lock(), "{}", text),
+    };
+}
+
+/// Best-effort level for a plain text line, based on the level names the
+/// server's pretty logger prints.
+fn guess_level(stream: LogStream, text: &str) -> LogLevel {
+    let plain = strip_ansi(text).to_ascii_uppercase();
+    let levels = [
+        ("FATAL", LogLevel::Fatal),
+        ("ERROR", LogLevel::Error),
+        ("WARN", LogLevel::Warn),
+        ("INFO", LogLevel::Info),
+        ("DEBUG", LogLevel::Debug),
+        ("TRACE", LogLevel::Trace),
+    ];
+    levels
+        .iter()
+        .find(|(name, _)| {
+            plain
+                .split(|c: char| !c.is_ascii_alphabetic())
+                .any(|word| word == *name)
+        })
+        .map(|(_, level)| *level)
+        .unwrap_or(match stream {
+            LogStream::Stdout => LogLevel::Info,
+            LogStream::Stderr => LogLevel::Warn,
+        })
+}
+
+/// Removes ANSI escape sequences such as colours.
+pub fn strip_ansi(text: &str) -> String {
+    let mut out = String::with_capacity(text.len());
+    let mut chars = text.chars().peekable();
+    while let Some(c) = chars.next() {
+        if c == '\u{1b}' {
+            if chars.peek() == Some(&'[') {
+                chars.next();
+                for c in chars.by_ref() {
+                    if ('@'..='~').contains(&c) {
+                        break;
+                    }
+                }
+            }
+            continue;
+        }
+        out.push(c);
+    }
+    out
+}
+
+pub fn now_millis() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_millis() as u64)
+        .unwrap_or(0)
+}
+
+/// Buffered server lines after sequence number `since` (exclusive).
+pub fn lines_since(since: Option<u64>) -> Vec<LogLine> {
+    LOG_BUFFER
+        .lock()
+        .expect("LOG_BUFFER mutex should not be poisoned")
+        .query(&LogFilter {
+            since,
+            ..LogFilter::default()
+        })
+}
+
+/// Buffered server lines after sequence number `since` (exclusive) at
+/// `level` or above, optionally only those of one agent or subsystem.
+#[tauri::command]
+pub fn get_server_logs(
+    since: Option<u64>,
+    level: Option<LogLevel>,
+    agent_id: Option<String>,
+    src: Option<String>,
+) -> Vec<LogLine> {
+    let filter = LogFilter {
+        since,
+        level,
+        agent_id,
+        src,
+    };
+    LOG_BUFFER
+        .lock()
+        .expect("LOG_BUFFER mutex should not be poisoned")
+        .query(&filter)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn pino(level: u8, src: &str, agent_id: &str, msg: &str) -> String {
+        format!(
+            r#"{{"level":{},"time":1700000000000,"src":"{}","agentId":"{}","msg":"{}"}}"#,
+            level, src, agent_id, msg
+        )
+    }
+
+    fn messages(lines: &[LogLine]) -> Vec<&str> {
+        lines.iter().map(|line| line.message.as_str()).collect()
+    }
+
+    #[test]
+    fn drops_the_oldest_lines_beyond_capacity() {
+        let mut buffer = LogBuffer::new(3);
+        for index in 0..5 {
+            let line = buffer.push(LogStream::Stdout, format!("line {}", index));
+            assert_eq!(line.seq, index);
+        }
+        let lines = buffer.query(&LogFilter::default());
+        assert_eq!(messages(&lines), ["line 2", "line 3", "line 4"]);
+        // Sequence numbers keep counting across evictions.
+        assert_eq!(
+            lines.iter().map(|line| line.seq).collect::<Vec<_>>(),
+            [2, 3, 4]
+        );
+    }
+
+    #[test]
+    fn returns_only_lines_after_since() {
+        let mut buffer = LogBuffer::new(10);
+        for index in 0..4 {
+            buffer.push(LogStream::Stdout, format!("line {}", index));
+        }
+        let after = |since| {
+            buffer
+                .query(&LogFilter {
+                    since,
+                    ..LogFilter::default()
+                })
+                .iter()
+                .map(|line| line.seq)
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(after(None), [0, 1, 2, 3]);
+        assert_eq!(after(Some(1)), [2, 3]);
+        assert!(after(Some(3)).is_empty());
+    }
+
+    #[test]
+    fn filters_by_level_agent_and_src() {
+        let mut buffer = LogBuffer::new(10);
+        buffer.push(LogStream::Stdout, pino(20, "http", "a1", "debug a1"));
+        buffer.push(LogStream::Stdout, pino(30, "http", "a1", "info a1"));
+        buffer.push(LogStream::Stdout, pino(50, "db", "a2", "error a2"));
+        buffer.push(LogStream::Stdout, "plain INFO text".into());
+        buffer.push(LogStream::Stderr, "plain text on stderr".into());
+
+        let query = |filter: LogFilter| messages(&buffer.query(&filter)).join(", ");
+        assert_eq!(
+            query(LogFilter {
+                level: Some(LogLevel::Info),
+                ..LogFilter::default()
+            }),
+            "info a1, error a2, plain INFO text, plain text on stderr"
+        );
+        assert_eq!(
+            query(LogFilter {
+                level: Some(LogLevel::Warn),
+                ..LogFilter::default()
+            }),
+            "error a2, plain text on stderr"
+        );
+        // Plain text lines have no agent or subsystem, so they never match
+        // a filter on either.
+        assert_eq!(
+            query(LogFilter {
+                agent_id: Some("a1".into()),
+                ..LogFilter::default()
+            }),
+            "debug a1, info a1"
+        );
+        assert_eq!(
+            query(LogFilter {
+                src: Some("db".into()),
+                ..LogFilter::default()
+            }),
+            "error a2"
+        );
+        assert_eq!(
+            query(LogFilter {
+                since: Some(0),
+                level: Some(LogLevel::Info),
+                agent_id: Some("a1".into()),
+                src: Some("http".into()),
+            }),
+            "info a1"
+        );
+    }
+}
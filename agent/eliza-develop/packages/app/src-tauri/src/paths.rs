@@ -0,0 +1,46 @@
+use std::fs::{self, OpenOptions};
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+
+/// The directory Tauri's `app_config_dir` resolves to, for code that runs
+/// before the app is built or in headless mode.
+pub fn app_config_dir(identifier: &str) -> Option<PathBuf> {
+    dirs::config_dir().map(|dir| dir.join(identifier))
+}
+
+/// The directory Tauri's `app_data_dir` resolves to.
+pub fn app_data_dir(identifier: &str) -> Option<PathBuf> {
+    dirs::data_dir().map(|dir| dir.join(identifier))
+}
+
+/// The directory Tauri's `app_log_dir` resolves to.
+pub fn app_log_dir(identifier: &str) -> Option<PathBuf> {
+    #[cfg(target_os = "macos")]
+    let dir = dirs::home_dir().map(|dir| dir.join("Library/Logs").join(identifier));
+
+    #[cfg(not(target_os = "macos"))]
+    let dir = dirs::data_local_dir().map(|dir| dir.join(identifier).join("logs"));
+
+    dir
+}
+
+/// Writes `contents` to a file only the current user can read.
+pub fn write_private(path: &Path, contents: &[u8]) -> io::Result<()> {
+    if let Some(dir) = path.parent() {
+        fs::create_dir_all(dir)?;
+    }
+    let mut options = OpenOptions::new();
+    options.write(true).create(true).truncate(true);
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::{OpenOptionsExt, PermissionsExt};
+        options.mode(0o600);
+        // `mode` only applies to a new file; tighten one that already exists.
+        if path.exists() {
+            fs::set_permissions(path, fs::Permissions::from_mode(0o600))?;
+        }
+    }
+    // On Windows the per-user app data dir is already private to the user.
+    let mut file = options.open(path)?;
+    file.write_all(contents)
+}
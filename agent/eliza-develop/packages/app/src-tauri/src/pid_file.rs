@@ -0,0 +1,278 @@
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::io;
+use std::path::PathBuf;
+use std::sync::Mutex;
+
+use crate::logs::now_millis;
+use crate::process;
+
+/// How far the start time reported by the OS may drift from the recorded
+/// one. `ps` only reports whole seconds.
+const START_TIME_TOLERANCE_MS: u64 = 5_000;
+
+static PID_FILE: once_cell::sync::Lazy<Mutex<Option<PathBuf>>> =
+    once_cell::sync::Lazy::new(|| Mutex::new(None));
+
+/// Server left behind by an earlier session, see [`detect_orphan`].
+static ORPHAN: once_cell::sync::Lazy<Mutex<Option<ServerRecord>>> =
+    once_cell::sync::Lazy::new(|| Mutex::new(None));
+
+/// What the PID file remembers about the server the app spawned.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ServerRecord {
+    pub pid: u32,
+    /// Unix time in milliseconds when the server was spawned.
+    pub started_at: u64,
+    pub command: String,
+    pub port: u16,
+    /// The app process that spawned it.
+    pub app_pid: u32,
+    /// The boot the server was spawned in, where the OS reports one.
+    #[serde(default)]
+    pub boot_id: Option<String>,
+}
+
+/// Sets where the PID file lives. Nothing is recorded before this is called.
+pub fn init(path: PathBuf) {
+    *PID_FILE
+        .lock()
+        .expect("PID_FILE mutex should not be poisoned") = Some(path);
+}
+
+/// Records a freshly spawned server, replacing any earlier record.
+pub fn record(pid: u32, command: String, port: u16) {
+    let record = ServerRecord {
+        pid,
+        started_at: now_millis(),
+        command,
+        port,
+        app_pid: std::process::id(),
+        boot_id: boot_id(),
+    };
+    with_path(|path| {
+        let json = serde_json::to_string_pretty(&record)
+            .map_err(|err| io::Error::new(io::ErrorKind::InvalidData, err))?;
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir)?;
+        }
+        fs::write(path, json)
+    });
+}
+
+/// Removes the record of server `pid` once it is known to be gone. A record
+/// of any other server is left alone.
+pub fn clear(pid: u32) {
+    with_path(|path| {
+        let recorded = fs::read_to_string(path)
+            .ok()
+            .and_then(|json| serde_json::from_str::<ServerRecord>(&json).ok());
+        if recorded.is_some_and(|record| record.pid != pid) {
+            return Ok(());
+        }
+        match fs::remove_file(path) {
+            Err(err) if err.kind() != io::ErrorKind::NotFound => Err(err),
+            _ => Ok(()),
+        }
+    });
+}
+
+/// Looks for a server spawned by an earlier app session that is still
+/// running. A record whose process is gone, or whose PID now belongs to a
+/// process started at a different time, is discarded: only a process that
+/// matches both the PID and the start time is treated as ours, or what is
+/// left of it, see [`is_leftover_group`]. Nothing is stopped here; the app
+/// offers to.
+pub fn detect_orphan() -> Option<ServerRecord> {
+    let path = PID_FILE
+        .lock()
+        .expect("PID_FILE mutex should not be poisoned")
+        .clone()?;
+    let record: ServerRecord = serde_json::from_str(&fs::read_to_string(&path).ok()?).ok()?;
+    if record.app_pid == std::process::id() {
+        return None;
+    }
+    if !is_same_process(&record) && !is_leftover_group(&record) {
+        clear(record.pid);
+        return None;
+    }
+    println!(
+        "Found Eliza server (pid {}) left running by an earlier session",
+        record.pid
+    );
+    *ORPHAN.lock().expect("ORPHAN mutex should not be poisoned") = Some(record.clone());
+    Some(record)
+}
+
+/// On Linux the server's own process dies with the app, but what it forked
+/// lives on in its process group and can keep holding the port. A group
+/// whose leader is gone is only taken for that remainder in the boot the
+/// server was spawned in, and only if everything in it started after the
+/// server did, so a group that merely reuses the ID is never ours.
+fn is_leftover_group(record: &ServerRecord) -> bool {
+    if process_age_ms(record.pid).is_some() || !process::group_alive(record.pid) {
+        return false;
+    }
+    if record.boot_id.is_none() || record.boot_id != boot_id() {
+        return false;
+    }
+    let since_start = now_millis().saturating_sub(record.started_at);
+    group_ages_ms(record.pid).is_some_and(|ages| {
+        !ages.is_empty()
+            && ages
+                .iter()
+                .all(|age| *age <= since_start + START_TIME_TOLERANCE_MS)
+    })
+}
+
+/// The orphan found at startup, until it is reaped.
+pub fn orphan() -> Option<ServerRecord> {
+    ORPHAN
+        .lock()
+        .expect("ORPHAN mutex should not be poisoned")
+        .clone()
+}
+
+/// Forgets the orphan, after checking once more that its PID still belongs
+/// to the process we started, or its group to what is left of it. Returns
+/// it if so.
+pub fn take_orphan() -> Option<ServerRecord> {
+    let record = ORPHAN
+        .lock()
+        .expect("ORPHAN mutex should not be poisoned")
+        .take()?;
+    if is_same_process(&record) || is_leftover_group(&record) {
+        Some(record)
+    } else {
+        clear(record.pid);
+        None
+    }
+}
+
+fn is_same_process(record: &ServerRecord) -> bool {
+    let Some(elapsed_ms) = process_age_ms(record.pid) else {
+        return false;
+    };
+    let started_at = now_millis().saturating_sub(elapsed_ms);
+    started_at.abs_diff(record.started_at) <= START_TIME_TOLERANCE_MS
+}
+
+/// How long `pid` has been running, or `None` if there is no such process.
+#[cfg(unix)]
+fn process_age_ms(pid: u32) -> Option<u64> {
+    let output = std::process::Command::new("ps")
+        .args(["-o", "etime=", "-p", &pid.to_string()])
+        .output()
+        .ok()?;
+    if !output.status.success() {
+        return None;
+    }
+    parse_etime(String::from_utf8_lossy(&output.stdout).trim()).map(|secs| secs * 1000)
+}
+
+/// Without a way to read a process's start time we cannot tell our server
+/// from an unrelated process that reused its PID, so nothing is reaped.
+#[cfg(windows)]
+fn process_age_ms(_pid: u32) -> Option<u64> {
+    None
+}
+
+/// How long each process in the group led by `pid` has been running.
+#[cfg(unix)]
+fn group_ages_ms(pid: u32) -> Option<Vec<u64>> {
+    let output = std::process::Command::new("ps")
+        .args(["-A", "-o", "pgid=,etime="])
+        .output()
+        .ok()?;
+    if !output.status.success() {
+        return None;
+    }
+    Some(parse_group_ages(
+        &String::from_utf8_lossy(&output.stdout),
+        pid,
+    ))
+}
+
+#[cfg(windows)]
+fn group_ages_ms(_pid: u32) -> Option<Vec<u64>> {
+    None
+}
+
+/// Picks the elapsed times of group `pgid` out of `pgid etime` lines.
+#[cfg(unix)]
+fn parse_group_ages(output: &str, pgid: u32) -> Vec<u64> {
+    output
+        .lines()
+        .filter_map(|line| {
+            let mut fields = line.split_whitespace();
+            let group = fields.next()?.parse::<u32>().ok()?;
+            let secs = parse_etime(fields.next()?)?;
+            (group == pgid).then_some(secs * 1000)
+        })
+        .collect()
+}
+
+/// Identifies the current boot, so a record can be told apart from one
+/// written before a reboot.
+#[cfg(target_os = "linux")]
+fn boot_id() -> Option<String> {
+    let id = fs::read_to_string("/proc/sys/kernel/random/boot_id").ok()?;
+    Some(id.trim().to_string()).filter(|id| !id.is_empty())
+}
+
+#[cfg(not(target_os = "linux"))]
+fn boot_id() -> Option<String> {
+    None
+}
+
+/// Parses the `[[dd-]hh:]mm:ss` elapsed time printed by `ps`, in seconds.
+#[cfg(unix)]
+fn parse_etime(etime: &str) -> Option<u64> {
+    let (days, clock) = match etime.split_once('-') {
+        Some((days, clock)) => (days.parse::<u64>().ok()?, clock),
+        None => (0, etime),
+    };
+    let mut secs = 0;
+    for part in clock.split(':') {
+        secs = secs * 60 + part.parse::<u64>().ok()?;
+    }
+    Some(days * 86_400 + secs)
+}
+
+fn with_path(f: impl FnOnce(&PathBuf) -> io::Result<()>) {
+    let guard = PID_FILE
+        .lock()
+        .expect("PID_FILE mutex should not be poisoned");
+    if let Some(path) = guard.as_ref() {
+        if let Err(err) = f(path) {
+            eprintln!("Failed to update server PID file: {}", err);
+        }
+    }
+}
+
+/// The server left running by an earlier session, if one was found.
+#[tauri::command]
+pub fn get_orphan_server() -> Option<ServerRecord> {
+    orphan()
+}
+
+#[cfg(all(test, unix))]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_elapsed_times() {
+        assert_eq!(parse_etime("00:05"), Some(5));
+        assert_eq!(parse_etime("01:02:03"), Some(3723));
+        assert_eq!(parse_etime("2-00:00:01"), Some(172_801));
+        assert_eq!(parse_etime("soon"), None);
+    }
+
+    #[test]
+    fn picks_the_ages_of_one_group() {
+        let output = "    1 10-01:00:00\n  420       01:05\n  421       00:02\n  420    00:07\n";
+        assert_eq!(parse_group_ages(output, 420), vec![65_000, 7_000]);
+        assert!(parse_group_ages(output, 7).is_empty());
+    }
+}
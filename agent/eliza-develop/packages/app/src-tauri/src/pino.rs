@@ -0,0 +1,132 @@
+use serde_json::{Map, Value};
+
+use crate::logs::LogLevel;
+
+/// The fields of a pino JSON log line the app cares about.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PinoRecord {
+    pub level: LogLevel,
+    /// Unix time in milliseconds.
+    pub time: Option<u64>,
+    /// Subsystem, e.g. `http` or `service:message-bus`.
+    pub src: Option<String>,
+    pub agent_id: Option<String>,
+    pub message: String,
+}
+
+/// Parses a line written by the server's pino logger. Returns `None` for
+/// anything that is not a JSON object with a `level`, so callers can fall
+/// back to treating the line as plain text.
+pub fn parse(line: &str) -> Option<PinoRecord> {
+    let line = line.trim();
+    if !line.starts_with('{') {
+        return None;
+    }
+    let Value::Object(fields) = serde_json::from_str::<Value>(line).ok()? else {
+        return None;
+    };
+    let level = match fields.get("level")? {
+        Value::Number(n) => level_from_number(n.as_u64()?),
+        Value::String(s) => level_from_label(s)?,
+        _ => return None,
+    };
+    Some(PinoRecord {
+        level,
+        time: fields.get("time").and_then(Value::as_u64),
+        src: string_field(&fields, "src"),
+        agent_id: string_field(&fields, "agentId"),
+        message: string_field(&fields, "msg")
+            .or_else(|| string_field(&fields, "message"))
+            .unwrap_or_default(),
+    })
+}
+
+/// Maps pino's numeric levels, including the custom `log` (29),
+/// `progress` (28) and `success` (27) levels of the ElizaOS logger.
+fn level_from_number(level: u64) -> LogLevel {
+    match level {
+        60.. => LogLevel::Fatal,
+        50..=59 => LogLevel::Error,
+        40..=49 => LogLevel::Warn,
+        27..=39 => LogLevel::Info,
+        20..=26 => LogLevel::Debug,
+        _ => LogLevel::Trace,
+    }
+}
+
+fn level_from_label(label: &str) -> Option<LogLevel> {
+    Some(match label.to_ascii_lowercase().as_str() {
+        "fatal" => LogLevel::Fatal,
+        "error" => LogLevel::Error,
+        "warn" | "warning" => LogLevel::Warn,
+        "info" | "log" | "progress" | "success" => LogLevel::Info,
+        "debug" => LogLevel::Debug,
+        "trace" | "verbose" => LogLevel::Trace,
+        _ => return None,
+    })
+}
+
+fn string_field(fields: &Map<String, Value>, key: &str) -> Option<String> {
+    fields.get(key).and_then(Value::as_str).map(str::to_string)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_a_pino_line() {
+        let line =
+            r#"{"level":30,"time":1700000000000,"src":"http","agentId":"a1","msg":"Listening"}"#;
+        assert_eq!(
+            parse(line),
+            Some(PinoRecord {
+                level: LogLevel::Info,
+                time: Some(1_700_000_000_000),
+                src: Some("http".into()),
+                agent_id: Some("a1".into()),
+                message: "Listening".into(),
+            })
+        );
+        let record = parse(r#"  {"level":"WARNING","message":"Slow"}  "#).unwrap();
+        assert_eq!(record.level, LogLevel::Warn);
+        assert_eq!(record.message, "Slow");
+        assert_eq!(parse(r#"{"level":29}"#).unwrap().level, LogLevel::Info);
+        assert_eq!(parse(r#"{"level":99}"#).unwrap().level, LogLevel::Fatal);
+    }
+
+    #[test]
+    fn leaves_malformed_lines_to_the_plain_text_path() {
+        for line in [
+            "",
+            "   ",
+            "Server listening on port 3000",
+            "[2024-01-01] {\"level\":30}",
+            "{",
+            "{\"level\":30",
+            "{\"level\":30,}",
+            "{} trailing",
+            "{\"msg\":\"no level\"}",
+            "{\"level\":null}",
+            "{\"level\":-1}",
+            "{\"level\":30.5}",
+            "{\"level\":\"loud\"}",
+            "{\"level\":[30]}",
+            "[{\"level\":30}]",
+            "\u{feff}{\"level\":30}",
+        ] {
+            assert_eq!(parse(line), None, "{:?} should not parse", line);
+        }
+    }
+
+    #[test]
+    fn ignores_fields_of_the_wrong_type() {
+        let record =
+            parse(r#"{"level":50,"time":"yesterday","src":7,"agentId":{},"msg":["x"]}"#).unwrap();
+        assert_eq!(record.level, LogLevel::Error);
+        assert_eq!(record.time, None);
+        assert_eq!(record.src, None);
+        assert_eq!(record.agent_id, None);
+        assert_eq!(record.message, "");
+    }
+}
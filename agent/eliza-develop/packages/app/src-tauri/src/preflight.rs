@@ -0,0 +1,394 @@
+use serde::Serialize;
+use std::env;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+use crate::config;
+use crate::error::AppError;
+use crate::health::{self, ProbeResult};
+use crate::launcher::{self, Launcher};
+
+/// Oldest runtimes ElizaOS is known to work with.
+const MIN_BUN_VERSION: Version = Version(1, 2, 0);
+const MIN_NODE_VERSION: Version = Version(23, 3, 0);
+
+/// Below this much free space the server is likely to fail writing its
+/// database; below the warning threshold it soon will.
+const MIN_FREE_BYTES: u64 = 256 * 1024 * 1024;
+const WARN_FREE_BYTES: u64 = 1024 * 1024 * 1024;
+
+/// Same variable the server reads for its data directory.
+const DATA_DIR_ENV: &str = "ELIZA_DATA_DIR";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub enum CheckName {
+    Bun,
+    Node,
+    Launcher,
+    DataDir,
+    DiskSpace,
+    Port,
+    Remote,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub enum CheckStatus {
+    Pass,
+    Warn,
+    Fail,
+    /// The check does not apply on this platform.
+    Skipped,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Check {
+    pub name: CheckName,
+    pub status: CheckStatus,
+    pub message: String,
+    pub hint: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PreflightReport {
+    /// No check failed; warnings do not keep the server from starting.
+    pub passed: bool,
+    pub checks: Vec<Check>,
+}
+
+impl PreflightReport {
+    pub fn failures(&self) -> impl Iterator<Item = &Check> {
+        self.checks
+            .iter()
+            .filter(|check| check.status == CheckStatus::Fail)
+    }
+}
+
+/// Runs every check. Takes a few hundred milliseconds, since it runs the
+/// runtimes and probes the port, so it must not run on the main thread. In
+/// remote mode nothing runs locally and only the remote server is checked.
+pub fn run() -> PreflightReport {
+    if config::current().is_remote() {
+        return report(vec![check_remote()]);
+    }
+    let data_dir = data_dir();
+    let launcher = launcher::resolve(config::current().launcher.as_deref());
+    report(vec![
+        check_bun(launcher.as_ref().ok()),
+        check_node(),
+        check_launcher(&launcher),
+        check_data_dir(&data_dir),
+        check_disk_space(&data_dir),
+        check_port(),
+    ])
+}
+
+fn report(checks: Vec<Check>) -> PreflightReport {
+    PreflightReport {
+        passed: !checks.iter().any(|check| check.status == CheckStatus::Fail),
+        checks,
+    }
+}
+
+/// A missing bun only fails when the resolved launcher runs on it; a
+/// launcher that could not be resolved is reported by its own check.
+fn check_bun(launcher: Option<&Launcher>) -> Check {
+    match runtime_version("bun") {
+        None if launcher.is_some_and(Launcher::runs_on_bun) => fail(
+            CheckName::Bun,
+            "bun is not installed".into(),
+            "Install bun from https://bun.sh; the ElizaOS CLI is run with it.",
+        ),
+        None => warn(
+            CheckName::Bun,
+            "bun is not installed".into(),
+            Some("Install bun from https://bun.sh if a plugin needs it.".into()),
+        ),
+        Some(Err(message)) => warn(CheckName::Bun, message, None),
+        Some(Ok(version)) if version < MIN_BUN_VERSION => warn(
+            CheckName::Bun,
+            format!("bun {} is older than {}", version, MIN_BUN_VERSION),
+            Some("Run `bun upgrade`.".into()),
+        ),
+        Some(Ok(version)) => pass(CheckName::Bun, format!("bun {}", version)),
+    }
+}
+
+fn check_node() -> Check {
+    match runtime_version("node") {
+        None => warn(
+            CheckName::Node,
+            "node is not installed".into(),
+            Some("Only needed by plugins that do not run under bun.".into()),
+        ),
+        Some(Err(message)) => warn(CheckName::Node, message, None),
+        Some(Ok(version)) if version < MIN_NODE_VERSION => warn(
+            CheckName::Node,
+            format!("node {} is older than {}", version, MIN_NODE_VERSION),
+            Some(format!("Install node {} or newer.", MIN_NODE_VERSION)),
+        ),
+        Some(Ok(version)) => pass(CheckName::Node, format!("node {}", version)),
+    }
+}
+
+fn check_launcher(launcher: &Result<Launcher, AppError>) -> Check {
+    match launcher {
+        Ok(launcher) => pass(CheckName::Launcher, launcher.display()),
+        Err(err) => fail(CheckName::Launcher, err.to_string(), &err.hint()),
+    }
+}
+
+fn check_data_dir(dir: &Path) -> Check {
+    let probe = dir.join(".eliza-desktop-write-test");
+    let result = fs::create_dir_all(dir)
+        .and_then(|()| fs::write(&probe, b"ok"))
+        .and_then(|()| fs::remove_file(&probe));
+    match result {
+        Ok(()) => pass(CheckName::DataDir, format!("{} is writable", dir.display())),
+        Err(err) => fail(
+            CheckName::DataDir,
+            format!("{} is not writable: {}", dir.display(), err),
+            &format!(
+                "Fix the permissions of the directory or point {} somewhere writable.",
+                DATA_DIR_ENV
+            ),
+        ),
+    }
+}
+
+fn check_disk_space(dir: &Path) -> Check {
+    // The directory itself may not exist yet.
+    let Some(existing) = dir.ancestors().find(|dir| dir.exists()) else {
+        return skipped(CheckName::DiskSpace, "no existing directory to check");
+    };
+    match free_bytes(existing) {
+        None => skipped(CheckName::DiskSpace, "not supported on this platform"),
+        Some(Err(err)) => warn(
+            CheckName::DiskSpace,
+            format!("Could not read free disk space: {}", err),
+            None,
+        ),
+        Some(Ok(free)) => {
+            let message = format!("{} MiB free", free / (1024 * 1024));
+            if free < MIN_FREE_BYTES {
+                fail(
+                    CheckName::DiskSpace,
+                    message,
+                    "Free up disk space; the server stores its database on this disk.",
+                )
+            } else if free < WARN_FREE_BYTES {
+                warn(
+                    CheckName::DiskSpace,
+                    message,
+                    Some("Disk space is running low.".into()),
+                )
+            } else {
+                pass(CheckName::DiskSpace, message)
+            }
+        }
+    }
+}
+
+fn check_port() -> Check {
+    let config = config::current();
+    match health::probe(&config.api_url()) {
+        ProbeResult::NotRunning => pass(CheckName::Port, format!("port {} is free", config.port)),
+        ProbeResult::Starting | ProbeResult::Healthy { .. } => pass(
+            CheckName::Port,
+            format!(
+                "an Eliza server is already listening on port {}",
+                config.port
+            ),
+        ),
+        ProbeResult::Unauthorized => fail(
+            CheckName::Port,
+            format!(
+                "the Eliza server on port {} rejected the API key",
+                config.port
+            ),
+            &format!(
+                "Stop that server or set {} to its token.",
+                config::AUTH_TOKEN_ENV
+            ),
+        ),
+        ProbeResult::Foreign { reason } if config.auto_port => warn(
+            CheckName::Port,
+            format!(
+                "port {} is held by another process: {}",
+                config.port, reason
+            ),
+            Some("A free port will be picked on the next launch.".into()),
+        ),
+        ProbeResult::Foreign { reason } => fail(
+            CheckName::Port,
+            format!(
+                "port {} is held by another process: {}",
+                config.port, reason
+            ),
+            &format!(
+                "Stop the program using port {} or set {} to a free port.",
+                config.port,
+                config::PORT_ENV
+            ),
+        ),
+    }
+}
+
+fn check_remote() -> Check {
+    let url = config::current().api_url();
+    let unreachable_hint = "Check that the server is running and reachable from this machine.";
+    match health::probe(&url) {
+        ProbeResult::Healthy { version, .. } => pass(
+            CheckName::Remote,
+            format!("Eliza server {} at {}", version, url),
+        ),
+        ProbeResult::Starting => warn(
+            CheckName::Remote,
+            format!("the Eliza server at {} is still starting", url),
+            None,
+        ),
+        ProbeResult::Unauthorized => fail(
+            CheckName::Remote,
+            format!("the Eliza server at {} rejected the API key", url),
+            &format!(
+                "Set {} to the token the server was started with.",
+                config::AUTH_TOKEN_ENV
+            ),
+        ),
+        ProbeResult::NotRunning => fail(
+            CheckName::Remote,
+            format!("nothing answers at {}", url),
+            unreachable_hint,
+        ),
+        ProbeResult::Foreign { reason } => fail(
+            CheckName::Remote,
+            format!("{} is not an Eliza server: {}", url, reason),
+            unreachable_hint,
+        ),
+    }
+}
+
+/// Where the server keeps its data: `ELIZA_DATA_DIR`, or `.eliza` in the
+/// directory it is started from, which is ours.
+pub fn data_dir() -> PathBuf {
+    match env::var_os(DATA_DIR_ENV).filter(|dir| !dir.is_empty()) {
+        Some(dir) => PathBuf::from(dir),
+        None => env::current_dir().unwrap_or_default().join(".eliza"),
+    }
+}
+
+/// `None` when `program` is not on `PATH`.
+fn runtime_version(program: &str) -> Option<Result<Version, String>> {
+    let path = launcher::find_on_path(program)?;
+    let output = match Command::new(&path).arg("--version").output() {
+        Ok(output) => output,
+        Err(err) => return Some(Err(format!("Failed to run {}: {}", path.display(), err))),
+    };
+    let text = String::from_utf8_lossy(&output.stdout);
+    Some(
+        Version::parse(text.trim())
+            .ok_or_else(|| format!("Unrecognised {} version {:?}", program, text.trim())),
+    )
+}
+
+#[cfg(unix)]
+fn free_bytes(path: &Path) -> Option<io::Result<u64>> {
+    use std::ffi::CString;
+    use std::os::unix::ffi::OsStrExt;
+
+    let path = match CString::new(path.as_os_str().as_bytes()) {
+        Ok(path) => path,
+        Err(err) => return Some(Err(io::Error::new(io::ErrorKind::InvalidInput, err))),
+    };
+    // SAFETY: `path` is a valid C string and `stat` is only read after
+    // statvfs reports success.
+    unsafe {
+        let mut stat: libc::statvfs = std::mem::zeroed();
+        if libc::statvfs(path.as_ptr(), &mut stat) != 0 {
+            return Some(Err(io::Error::last_os_error()));
+        }
+        #[allow(clippy::unnecessary_cast)]
+        Some(Ok(stat.f_bavail as u64 * stat.f_frsize as u64))
+    }
+}
+
+#[cfg(windows)]
+fn free_bytes(_path: &Path) -> Option<io::Result<u64>> {
+    None
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+struct Version(u32, u32, u32);
+
+impl Version {
+    /// Parses `1.2.3` or `v1.2.3`, ignoring any pre-release suffix.
+    fn parse(text: &str) -> Option<Self> {
+        let mut parts = text.trim_start_matches('v').splitn(3, '.').map(|part| {
+            part.chars()
+                .take_while(char::is_ascii_digit)
+                .collect::<String>()
+                .parse::<u32>()
+                .ok()
+        });
+        Some(Version(
+            parts.next()??,
+            parts.next().flatten().unwrap_or(0),
+            parts.next().flatten().unwrap_or(0),
+        ))
+    }
+}
+
+impl std::fmt::Display for Version {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}.{}.{}", self.0, self.1, self.2)
+    }
+}
+
+fn pass(name: CheckName, message: String) -> Check {
+    Check {
+        name,
+        status: CheckStatus::Pass,
+        message,
+        hint: None,
+    }
+}
+
+fn warn(name: CheckName, message: String, hint: Option<String>) -> Check {
+    Check {
+        name,
+        status: CheckStatus::Warn,
+        message,
+        hint,
+    }
+}
+
+fn fail(name: CheckName, message: String, hint: &str) -> Check {
+    Check {
+        name,
+        status: CheckStatus::Fail,
+        message,
+        hint: Some(hint.to_string()),
+    }
+}
+
+fn skipped(name: CheckName, message: &str) -> Check {
+    Check {
+        name,
+        status: CheckStatus::Skipped,
+        message: message.to_string(),
+        hint: None,
+    }
+}
+
+/// Runs the preflight checks for the wrapper screen.
+#[tauri::command]
+pub async fn run_preflight() -> Result<PreflightReport, String> {
+    tauri::async_runtime::spawn_blocking(run)
+        .await
+        .map_err(|err| err.to_string())
+}
@@ -0,0 +1,252 @@
+use serde::Serialize;
+use std::env;
+use std::io;
+#[cfg(windows)]
+use std::process::Stdio;
+use std::process::{Child, Command};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use crate::supervisor::ExitKind;
+
+/// Grace period given to the server to exit after SIGTERM before it is killed.
+pub const DEFAULT_SHUTDOWN_GRACE: Duration = Duration::from_secs(10);
+
+/// Environment variable overriding [`DEFAULT_SHUTDOWN_GRACE`], in seconds.
+pub const SHUTDOWN_GRACE_ENV: &str = "ELIZA_SHUTDOWN_GRACE_SECS";
+
+const EXIT_POLL_INTERVAL: Duration = Duration::from_millis(100);
+
+/// Result of stopping the server process.
+#[derive(Debug, Clone, Serialize)]
+#[serde(
+    tag = "type",
+    rename_all = "camelCase",
+    rename_all_fields = "camelCase"
+)]
+pub enum ShutdownOutcome {
+    NotRunning,
+    /// The process exited on its own within the grace period. `exit` is
+    /// unknown for processes that are not our child.
+    Graceful {
+        exit: Option<ExitKind>,
+        elapsed_ms: u64,
+    },
+    /// The process ignored SIGTERM and was killed after the grace period.
+    Killed {
+        exit: Option<ExitKind>,
+    },
+    Failed {
+        message: String,
+    },
+}
+
+pub fn shutdown_grace() -> Duration {
+    env::var(SHUTDOWN_GRACE_ENV)
+        .ok()
+        .and_then(|value| value.trim().parse::<u64>().ok())
+        .map(Duration::from_secs)
+        .unwrap_or(DEFAULT_SHUTDOWN_GRACE)
+}
+
+/// Configures `command` so the server runs in its own session and process
+/// group, which lets us signal everything `elizaos start` forks at once.
+///
+/// On Linux the server also gets SIGTERM when the thread that spawned it
+/// exits. Only the direct child receives that signal, so what it forked can
+/// outlive an app that was killed outright; the next launch finds that
+/// remainder through the PID file and offers to stop it, see
+/// `pid_file::detect_orphan`.
+#[cfg(unix)]
+pub fn isolate(command: &mut Command) {
+    use std::os::unix::process::CommandExt;
+
+    #[cfg(target_os = "linux")]
+    let parent = std::process::id() as libc::pid_t;
+
+    // SAFETY: the closure only calls async-signal-safe functions.
+    unsafe {
+        command.pre_exec(move || {
+            if libc::setsid() == -1 {
+                return Err(io::Error::last_os_error());
+            }
+            #[cfg(target_os = "linux")]
+            {
+                if libc::prctl(libc::PR_SET_PDEATHSIG, libc::SIGTERM) == -1 {
+                    return Err(io::Error::last_os_error());
+                }
+                // The parent may have died before prctl took effect.
+                if libc::getppid() != parent {
+                    libc::_exit(1);
+                }
+            }
+            Ok(())
+        });
+    }
+}
+
+#[cfg(windows)]
+pub fn isolate(command: &mut Command) {
+    use std::os::windows::process::CommandExt;
+
+    const CREATE_NEW_PROCESS_GROUP: u32 = 0x0000_0200;
+    command.creation_flags(CREATE_NEW_PROCESS_GROUP);
+}
+
+/// Asks the server's process group to terminate and waits up to `grace` for
+/// it to exit, escalating to a hard kill of the whole group once the
+/// deadline passes.
+pub fn terminate(child: &mut Child, grace: Duration) -> ShutdownOutcome {
+    let pid = child.id();
+    let mut exit = match child.try_wait() {
+        Ok(status) => status.map(ExitKind::classify),
+        Err(err) => return failed(err),
+    };
+    if exit.is_some() && !group_alive(pid) {
+        return ShutdownOutcome::NotRunning;
+    }
+
+    let started = Instant::now();
+    if let Err(err) = signal_group(pid, Signal::Terminate) {
+        eprintln!("Failed to send SIGTERM to Eliza server: {}", err);
+    } else {
+        let deadline = started + grace;
+        while Instant::now() < deadline {
+            if exit.is_none() {
+                match child.try_wait() {
+                    Ok(status) => exit = status.map(ExitKind::classify),
+                    Err(err) => return failed(err),
+                }
+            }
+            if let Some(exit) = exit.filter(|_| !group_alive(pid)) {
+                return ShutdownOutcome::Graceful {
+                    exit: Some(exit),
+                    elapsed_ms: started.elapsed().as_millis() as u64,
+                };
+            }
+            thread::sleep(EXIT_POLL_INTERVAL);
+        }
+        eprintln!("Eliza server did not exit within {:?}, killing it", grace);
+    }
+
+    if let Err(err) = kill_group(pid) {
+        eprintln!("Failed to kill Eliza server process group: {}", err);
+        if let Err(err) = child.kill() {
+            return failed(err);
+        }
+    }
+    if exit.is_none() {
+        exit = child.wait().ok().map(ExitKind::classify);
+    }
+    ShutdownOutcome::Killed { exit }
+}
+
+/// Like [`terminate`], for a server group we no longer hold a [`Child`] for,
+/// such as one left behind by an earlier app session.
+pub fn terminate_group(pid: u32, grace: Duration) -> ShutdownOutcome {
+    if !group_alive(pid) {
+        return ShutdownOutcome::NotRunning;
+    }
+    let started = Instant::now();
+    if let Err(err) = signal_group(pid, Signal::Terminate) {
+        if is_no_such_process(&err) {
+            return ShutdownOutcome::NotRunning;
+        }
+        eprintln!("Failed to send SIGTERM to Eliza server: {}", err);
+    } else {
+        let deadline = started + grace;
+        while Instant::now() < deadline {
+            if !group_alive(pid) {
+                return ShutdownOutcome::Graceful {
+                    exit: None,
+                    elapsed_ms: started.elapsed().as_millis() as u64,
+                };
+            }
+            thread::sleep(EXIT_POLL_INTERVAL);
+        }
+        eprintln!("Eliza server did not exit within {:?}, killing it", grace);
+    }
+    match kill_group(pid) {
+        Ok(()) => ShutdownOutcome::Killed { exit: None },
+        Err(err) => failed(err),
+    }
+}
+
+/// Kills whatever is left of the process group led by `pid`. Used after the
+/// server exits so forked workers do not keep holding its port.
+pub fn kill_group(pid: u32) -> io::Result<()> {
+    match signal_group(pid, Signal::Kill) {
+        Err(err) if is_no_such_process(&err) => Ok(()),
+        result => result,
+    }
+}
+
+enum Signal {
+    Terminate,
+    Kill,
+}
+
+#[cfg(unix)]
+fn signal_group(pid: u32, signal: Signal) -> io::Result<()> {
+    let signal = match signal {
+        Signal::Terminate => libc::SIGTERM,
+        Signal::Kill => libc::SIGKILL,
+    };
+    // SAFETY: kill(2) has no memory-safety preconditions. A negative pid
+    // targets the process group that `isolate` made the server lead.
+    if unsafe { libc::kill(-(pid as libc::pid_t), signal) } == 0 {
+        Ok(())
+    } else {
+        Err(io::Error::last_os_error())
+    }
+}
+
+#[cfg(windows)]
+fn signal_group(pid: u32, signal: Signal) -> io::Result<()> {
+    match signal {
+        Signal::Terminate => Err(io::Error::new(
+            io::ErrorKind::Unsupported,
+            "graceful termination is not supported on this platform",
+        )),
+        Signal::Kill => {
+            let status = Command::new("taskkill")
+                .args(["/PID", &pid.to_string(), "/T", "/F"])
+                .stdout(Stdio::null())
+                .stderr(Stdio::null())
+                .status()?;
+            if status.success() {
+                Ok(())
+            } else {
+                Err(io::Error::new(io::ErrorKind::NotFound, "taskkill failed"))
+            }
+        }
+    }
+}
+
+/// Whether anything is left in the process group led by `pid`.
+#[cfg(unix)]
+pub fn group_alive(pid: u32) -> bool {
+    // SAFETY: signal 0 only checks whether the group exists.
+    unsafe { libc::kill(-(pid as libc::pid_t), 0) == 0 }
+}
+
+#[cfg(windows)]
+pub fn group_alive(_pid: u32) -> bool {
+    false
+}
+
+#[cfg(unix)]
+fn is_no_such_process(err: &io::Error) -> bool {
+    err.raw_os_error() == Some(libc::ESRCH)
+}
+
+#[cfg(windows)]
+fn is_no_such_process(err: &io::Error) -> bool {
+    err.kind() == io::ErrorKind::NotFound
+}
+
+fn failed(err: io::Error) -> ShutdownOutcome {
+    ShutdownOutcome::Failed {
+        message: err.to_string(),
+    }
+}
@@ -0,0 +1,907 @@
+use std::collections::HashMap;
+use std::io::{self, Read, Write};
+use std::net::{Ipv4Addr, SocketAddr, TcpListener, TcpStream};
+use std::sync::Mutex;
+use std::thread;
+use std::time::Duration;
+use tauri::http::{header, HeaderMap, Request, Response, StatusCode};
+use tauri::utils::config::Csp;
+use tauri::{AppHandle, Manager, Url};
+
+use crate::health::API_KEY_HEADER;
+use crate::state::ServerState;
+use crate::{config, csp};
+
+/// Path that trades a one-time code from the app for the session cookie.
+const SESSION_PATH: &str = "/__eliza/session";
+/// HttpOnly, so scripts in the client cannot read it.
+const SESSION_COOKIE: &str = "eliza-session";
+
+const CONNECT_TIMEOUT: Duration = Duration::from_secs(10);
+/// Long enough for a model call or a socket.io long poll to answer. Bodies
+/// are streamed without a time limit.
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(120);
+
+const MAX_HEAD_BYTES: usize = 64 * 1024;
+const MAX_HEADERS: usize = 100;
+/// Links handed out but not yet opened.
+const MAX_CODES: usize = 16;
+
+/// Paths the client uses: its own routes and assets, the REST API, socket.io
+/// and media. Anything else on the server, such as routes plugins mount at
+/// the root, cannot be reached through the proxy.
+const ALLOWED_PREFIXES: &[&str] = &[
+    "/api/",
+    "/socket.io/",
+    "/media/",
+    "/assets/",
+    "/images/",
+    "/chat/",
+    "/settings/",
+    "/group/",
+    "/agents/",
+];
+const ALLOWED_PATHS: &[&str] = &[
+    "/",
+    "/index.html",
+    "/create",
+    "/logs",
+    "/settings",
+    "/favicon.ico",
+    "/elizaos-avatar.png",
+    "/elizaos-icon.png",
+    "/elizaos-logo-light.png",
+    "/elizaos.webp",
+];
+
+/// Request headers that belong to the hop between the webview and the
+/// proxy, or that the proxy sets itself. `Accept-Encoding` is left to ureq,
+/// which only decodes what it asked for. `Content-Length` is kept: the body
+/// is streamed, and ureq sends it as declared.
+const DROPPED_REQUEST_HEADERS: &[&str] = &[
+    "host",
+    "connection",
+    "keep-alive",
+    "upgrade",
+    "transfer-encoding",
+    "accept-encoding",
+    "origin",
+    "referer",
+    "cookie",
+    "x-api-key",
+];
+const DROPPED_RESPONSE_HEADERS: &[&str] = &[
+    "connection",
+    "keep-alive",
+    "transfer-encoding",
+    "content-length",
+];
+
+struct Proxy {
+    addr: SocketAddr,
+    session: String,
+    /// Sent with every page, since Tauri only adds its CSP to pages it
+    /// serves itself.
+    csp: String,
+    /// One-time codes handed to the wrapper page, with the path each opens.
+    codes: HashMap<String, String>,
+}
+
+static PROXY: Mutex<Option<Proxy>> = Mutex::new(None);
+
+/// Shared by every request, so connections to the server are reused.
+static AGENT: once_cell::sync::Lazy<ureq::Agent> = once_cell::sync::Lazy::new(|| {
+    ureq::Agent::config_builder()
+        .timeout_connect(Some(CONNECT_TIMEOUT))
+        .timeout_recv_response(Some(REQUEST_TIMEOUT))
+        .http_status_as_error(false)
+        .max_redirects(0)
+        .build()
+        .into()
+});
+
+/// The wrapper page, to return to when the server goes away.
+static APP_URL: Mutex<Option<Url>> = Mutex::new(None);
+
+/// Why the proxy answered a request itself.
+#[derive(Debug)]
+struct Rejection {
+    status: StatusCode,
+    message: String,
+}
+
+impl Rejection {
+    fn new(status: StatusCode, message: impl Into<String>) -> Self {
+        Self {
+            status,
+            message: message.into(),
+        }
+    }
+}
+
+enum Admitted {
+    /// Forward to the server.
+    Forward,
+    /// Set the session cookie and open this path.
+    Session(String),
+}
+
+/// Serves the Eliza client to the main window from a loopback port. Each
+/// request is forwarded to the server with the API key added, and response
+/// bodies are streamed, so server-sent events and socket.io long polling
+/// work. The client never sees the key: the wrapper page opens it through a
+/// one-time link that sets an HttpOnly session cookie, and requests without
+/// that cookie are refused, so other local processes cannot use the proxy
+/// to get around the key. Pages get the app's CSP from `base_csp`, with the
+/// proxy's origin allowed.
+pub fn start(base_csp: Option<Csp>) -> io::Result<()> {
+    let listener = TcpListener::bind((Ipv4Addr::LOCALHOST, 0))?;
+    let addr = listener.local_addr()?;
+    let csp = csp::with_origins(base_csp, &[format!("http://{}", addr)])
+        .map_err(|err| io::Error::new(io::ErrorKind::InvalidInput, err.to_string()))?;
+    *PROXY.lock().expect("PROXY mutex should not be poisoned") = Some(Proxy {
+        addr,
+        session: crate::auth::generate()?,
+        csp: csp.to_string(),
+        codes: HashMap::new(),
+    });
+    thread::Builder::new()
+        .name("eliza-proxy".into())
+        .spawn(move || {
+            for stream in listener.incoming() {
+                match stream {
+                    Ok(stream) => {
+                        let spawned = thread::Builder::new()
+                            .name("eliza-proxy-request".into())
+                            .spawn(move || {
+                                if let Err(err) = serve(stream) {
+                                    if !matches!(
+                                        err.kind(),
+                                        io::ErrorKind::BrokenPipe
+                                            | io::ErrorKind::ConnectionReset
+                                            | io::ErrorKind::ConnectionAborted
+                                    ) {
+                                        eprintln!("Failed to proxy a request: {}", err);
+                                    }
+                                }
+                            });
+                        if let Err(err) = spawned {
+                            eprintln!("Failed to start a proxy thread: {}", err);
+                        }
+                    }
+                    Err(err) => eprintln!("Failed to accept a proxy connection: {}", err),
+                }
+            }
+        })?;
+    println!("Serving the Eliza client on http://{}", addr);
+    Ok(())
+}
+
+/// Origin the client is served from, once the proxy runs.
+pub fn origin() -> Option<String> {
+    PROXY
+        .lock()
+        .expect("PROXY mutex should not be poisoned")
+        .as_ref()
+        .map(|proxy| format!("http://{}", proxy.addr))
+}
+
+/// Answers one request, then closes the connection so every request gets
+/// the same checks.
+fn serve(mut stream: TcpStream) -> io::Result<()> {
+    stream.set_read_timeout(Some(REQUEST_TIMEOUT))?;
+    let Some((head, mut body)) = read_head(&mut stream)? else {
+        return Ok(());
+    };
+    let parsed = parse_head(&head).and_then(|(request, length)| {
+        let mut guard = PROXY.lock().expect("PROXY mutex should not be poisoned");
+        let proxy = guard
+            .as_mut()
+            .ok_or_else(|| Rejection::new(StatusCode::SERVICE_UNAVAILABLE, "Not ready"))?;
+        admit(&request, proxy).map(|admitted| (request, length, admitted))
+    });
+    let (request, length) = match parsed {
+        Ok((request, length, Admitted::Forward)) => (request, length),
+        Ok((_, _, Admitted::Session(path))) => return write_session(&mut stream, &path),
+        Err(rejection) => {
+            return write_text(&mut stream, rejection.status, &rejection.message);
+        }
+    };
+
+    // The body goes to the server as it arrives: what was read with the
+    // head, then the rest from the connection.
+    body.truncate(length as usize);
+    let missing = length - body.len() as u64;
+    let mut reader = io::Cursor::new(body).chain((&stream).take(missing));
+    let path = request.uri().path().to_string();
+    let forwarded = forward(request, length, &mut reader);
+    match forwarded {
+        Ok(response) => write_upstream(&mut stream, response),
+        Err(err) => {
+            eprintln!("Failed to proxy {}: {}", path, err);
+            write_text(
+                &mut stream,
+                StatusCode::BAD_GATEWAY,
+                &format!("The Eliza server did not answer: {}", err),
+            )
+        }
+    }
+}
+
+/// Reads up to the end of the request head. Returns the head and whatever
+/// was read past it, or `None` if the connection closed first.
+fn read_head(stream: &mut impl Read) -> io::Result<Option<(Vec<u8>, Vec<u8>)>> {
+    let mut buffer = Vec::new();
+    let mut chunk = [0u8; 4096];
+    loop {
+        let read = stream.read(&mut chunk)?;
+        if read == 0 {
+            return Ok(None);
+        }
+        buffer.extend_from_slice(&chunk[..read]);
+        if let Some(end) = buffer.windows(4).position(|window| window == b"\r\n\r\n") {
+            let rest = buffer.split_off(end + 4);
+            return Ok(Some((buffer, rest)));
+        }
+        if buffer.len() > MAX_HEAD_BYTES {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "request head is too large",
+            ));
+        }
+    }
+}
+
+/// Parses a request head into a request and the length of its body.
+fn parse_head(head: &[u8]) -> Result<(Request<()>, u64), Rejection> {
+    let bad = |message: &str| Rejection::new(StatusCode::BAD_REQUEST, message);
+    let mut headers = [httparse::EMPTY_HEADER; MAX_HEADERS];
+    let mut parsed = httparse::Request::new(&mut headers);
+    match parsed.parse(head) {
+        Ok(httparse::Status::Complete(_)) => {}
+        Ok(httparse::Status::Partial) => return Err(bad("Incomplete request")),
+        Err(err) => return Err(bad(&format!("Malformed request: {}", err))),
+    }
+    let (Some(method), Some(target)) = (parsed.method, parsed.path) else {
+        return Err(bad("Malformed request"));
+    };
+    if !target.starts_with('/') {
+        return Err(bad("Only origin-form request targets are accepted"));
+    }
+    let mut builder = Request::builder().method(method).uri(target);
+    for header in parsed.headers.iter() {
+        builder = builder.header(header.name, header.value);
+    }
+    let request = builder
+        .body(())
+        .map_err(|err| bad(&format!("Malformed request: {}", err)))?;
+
+    if request.headers().contains_key(header::TRANSFER_ENCODING) {
+        return Err(Rejection::new(
+            StatusCode::LENGTH_REQUIRED,
+            "Request bodies need a Content-Length",
+        ));
+    }
+    let length = match request.headers().get(header::CONTENT_LENGTH) {
+        None => 0,
+        Some(value) => value
+            .to_str()
+            .ok()
+            .and_then(|value| value.trim().parse::<u64>().ok())
+            .ok_or_else(|| bad("Invalid Content-Length"))?,
+    };
+    Ok((request, length))
+}
+
+/// Decides whether a request may reach the server.
+fn admit(request: &Request<()>, proxy: &mut Proxy) -> Result<Admitted, Rejection> {
+    let forbidden = |message: &str| Rejection::new(StatusCode::FORBIDDEN, message);
+    // A page on another site rebound to 127.0.0.1 sends its own host name.
+    let host = request
+        .headers()
+        .get(header::HOST)
+        .and_then(|host| host.to_str().ok());
+    if host != Some(proxy.addr.to_string().as_str()) {
+        return Err(forbidden("Unexpected Host header"));
+    }
+
+    let path = request.uri().path();
+    if path == SESSION_PATH {
+        let code = request
+            .uri()
+            .query()
+            .and_then(|query| query.split('&').find_map(|pair| pair.strip_prefix("code=")));
+        return code
+            .and_then(|code| proxy.codes.remove(code))
+            .map(Admitted::Session)
+            .ok_or_else(|| forbidden("This link has expired; open Eliza from the desktop app"));
+    }
+    if !has_session(request.headers(), &proxy.session) {
+        return Err(forbidden("Open Eliza from the desktop app"));
+    }
+    if !is_allowed(path) {
+        return Err(forbidden(&format!("{} is not served to the app", path)));
+    }
+    if request.headers().contains_key(header::UPGRADE) {
+        // socket.io stays on long polling when its upgrade fails.
+        return Err(Rejection::new(
+            StatusCode::NOT_IMPLEMENTED,
+            "WebSockets are not proxied",
+        ));
+    }
+    Ok(Admitted::Forward)
+}
+
+fn has_session(headers: &HeaderMap, session: &str) -> bool {
+    headers
+        .get_all(header::COOKIE)
+        .iter()
+        .filter_map(|value| value.to_str().ok())
+        .flat_map(|value| value.split(';'))
+        .filter_map(|pair| pair.trim().split_once('='))
+        .any(|(name, value)| name == SESSION_COOKIE && value == session)
+}
+
+/// The client's own cookies, without the proxy's.
+fn upstream_cookies(headers: &HeaderMap) -> Option<String> {
+    let cookies = headers
+        .get_all(header::COOKIE)
+        .iter()
+        .filter_map(|value| value.to_str().ok())
+        .flat_map(|value| value.split(';'))
+        .map(str::trim)
+        .filter(|pair| {
+            !pair.is_empty()
+                && pair
+                    .split_once('=')
+                    .is_none_or(|(name, _)| name != SESSION_COOKIE)
+        })
+        .collect::<Vec<_>>();
+    (!cookies.is_empty()).then(|| cookies.join("; "))
+}
+
+/// Sends `request` to the server with the `length` bytes of `body`.
+fn forward(
+    request: Request<()>,
+    length: u64,
+    body: &mut impl Read,
+) -> Result<Response<ureq::Body>, ureq::Error> {
+    let config = config::current();
+    let (parts, ()) = request.into_parts();
+    let path_and_query = parts
+        .uri
+        .path_and_query()
+        .map_or("/", |path_and_query| path_and_query.as_str());
+
+    let mut upstream = Request::builder().method(parts.method.clone()).uri(format!(
+        "{}{}",
+        config.api_url(),
+        path_and_query
+    ));
+    for (name, value) in &parts.headers {
+        if !DROPPED_REQUEST_HEADERS.contains(&name.as_str()) {
+            upstream = upstream.header(name, value);
+        }
+    }
+    if let Some(cookies) = upstream_cookies(&parts.headers) {
+        upstream = upstream.header(header::COOKIE, cookies);
+    }
+    if let Some(token) = &config.auth_token {
+        upstream = upstream.header(API_KEY_HEADER, token);
+    }
+
+    if length == 0 {
+        AGENT.run(upstream.body(())?)
+    } else {
+        AGENT.run(upstream.body(ureq::SendBody::from_reader(body))?)
+    }
+}
+
+/// Passes the server's response on, writing each part of the body as it
+/// arrives. Pages get the app's CSP on top of any the server sends.
+fn write_upstream(stream: &mut impl Write, response: Response<ureq::Body>) -> io::Result<()> {
+    let api_url = config::current().api_url();
+    let (origin, csp) = PROXY
+        .lock()
+        .expect("PROXY mutex should not be poisoned")
+        .as_ref()
+        .map(|proxy| (format!("http://{}", proxy.addr), proxy.csp.clone()))
+        .unwrap_or_default();
+    let (parts, body) = response.into_parts();
+
+    let mut headers = Vec::new();
+    for (name, value) in &parts.headers {
+        if DROPPED_RESPONSE_HEADERS.contains(&name.as_str()) {
+            continue;
+        }
+        // Keep redirects on the proxy instead of the server's origin.
+        if name == header::LOCATION {
+            if let Some(location) = value
+                .to_str()
+                .ok()
+                .and_then(|location| location.strip_prefix(&api_url))
+            {
+                headers.push((
+                    name.as_str(),
+                    format!("{}{}", origin, location).into_bytes(),
+                ));
+                continue;
+            }
+        }
+        headers.push((name.as_str(), value.as_bytes().to_vec()));
+    }
+    let is_html = parts
+        .headers
+        .get(header::CONTENT_TYPE)
+        .and_then(|value| value.to_str().ok())
+        .is_some_and(|value| {
+            value
+                .trim_start()
+                .to_ascii_lowercase()
+                .starts_with("text/html")
+        });
+    if is_html {
+        headers.push(("content-security-policy", csp.into_bytes()));
+    }
+    write_head(stream, parts.status, &headers)?;
+
+    let mut reader = body.into_reader();
+    let mut chunk = [0u8; 16 * 1024];
+    loop {
+        let read = reader.read(&mut chunk)?;
+        if read == 0 {
+            return Ok(());
+        }
+        stream.write_all(&chunk[..read])?;
+        stream.flush()?;
+    }
+}
+
+/// Writes a status line and headers. The connection closes after the body,
+/// which is how the webview knows where it ends.
+fn write_head(
+    stream: &mut impl Write,
+    status: StatusCode,
+    headers: &[(&str, Vec<u8>)],
+) -> io::Result<()> {
+    let mut head = format!(
+        "HTTP/1.1 {} {}\r\n",
+        status.as_u16(),
+        status.canonical_reason().unwrap_or("")
+    )
+    .into_bytes();
+    for (name, value) in headers {
+        head.extend_from_slice(name.as_bytes());
+        head.extend_from_slice(b": ");
+        head.extend_from_slice(value);
+        head.extend_from_slice(b"\r\n");
+    }
+    head.extend_from_slice(b"connection: close\r\n\r\n");
+    stream.write_all(&head)
+}
+
+fn write_text(stream: &mut impl Write, status: StatusCode, message: &str) -> io::Result<()> {
+    write_head(
+        stream,
+        status,
+        &[("content-type", b"text/plain; charset=utf-8".to_vec())],
+    )?;
+    stream.write_all(message.as_bytes())
+}
+
+fn write_session(stream: &mut impl Write, path: &str) -> io::Result<()> {
+    let session = PROXY
+        .lock()
+        .expect("PROXY mutex should not be poisoned")
+        .as_ref()
+        .map(|proxy| proxy.session.clone())
+        .unwrap_or_default();
+    // Lax, not Strict: the redirect that follows was started from the
+    // wrapper page, which is another site.
+    let cookie = format!(
+        "{}={}; Path=/; HttpOnly; SameSite=Lax",
+        SESSION_COOKIE, session
+    );
+    write_head(
+        stream,
+        StatusCode::SEE_OTHER,
+        &[
+            ("location", path.as_bytes().to_vec()),
+            ("set-cookie", cookie.into_bytes()),
+            ("cache-control", b"no-store".to_vec()),
+            ("content-length", b"0".to_vec()),
+        ],
+    )
+}
+
+fn is_allowed(path: &str) -> bool {
+    // Browsers resolve `..` themselves, but not its percent-encoded forms,
+    // and a server may decode `%2f` or take `\` as a separator.
+    let decoded = path
+        .to_ascii_lowercase()
+        .replace("%2e", ".")
+        .replace("%2f", "/")
+        .replace("%5c", "/")
+        .replace('\\', "/");
+    let escapes = decoded.split('/').any(|segment| segment == "..");
+    !escapes
+        && (ALLOWED_PATHS.contains(&path)
+            || ALLOWED_PREFIXES
+                .iter()
+                .any(|prefix| path.starts_with(prefix)))
+}
+
+/// Remembers the wrapper page the main window starts on.
+pub fn remember_app_url(url: Url) {
+    *APP_URL
+        .lock()
+        .expect("APP_URL mutex should not be poisoned") = Some(url);
+}
+
+/// The main window, if it currently shows the client.
+fn client_window(app: &AppHandle) -> Option<tauri::WebviewWindow> {
+    let origin = origin()?;
+    let window = app.get_webview_window("main")?;
+    let url = window.url().ok()?;
+    url.as_str().starts_with(&origin).then_some(window)
+}
+
+/// Sends the main window back to the wrapper when the server stops being
+/// usable, so it can show what happened and bring the client back later.
+pub fn follow_state(app: &AppHandle, state: &ServerState) {
+    if matches!(
+        state,
+        ServerState::Ready { .. } | ServerState::WaitingForHealth { .. }
+    ) {
+        return;
+    }
+    let Some(window) = client_window(app) else {
+        return;
+    };
+    let app_url = APP_URL
+        .lock()
+        .expect("APP_URL mutex should not be poisoned")
+        .clone();
+    if let Some(app_url) = app_url {
+        if let Err(err) = window.navigate(app_url) {
+            eprintln!("Failed to return to the app page: {}", err);
+        }
+    }
+}
+
+/// Shows `path` of the client if the main window already displays it.
+/// Returns whether it did.
+pub fn open(app: &AppHandle, path: &str) -> bool {
+    let Some(window) = client_window(app) else {
+        return false;
+    };
+    let Some(origin) = origin() else {
+        return false;
+    };
+    match Url::parse(&format!("{}{}", origin, path)) {
+        Ok(url) => match window.navigate(url) {
+            Ok(()) => true,
+            Err(err) => {
+                eprintln!("Failed to open {} in the client: {}", path, err);
+                false
+            }
+        },
+        Err(err) => {
+            eprintln!("Failed to open {} in the client: {}", path, err);
+            false
+        }
+    }
+}
+
+/// A one-time link that opens `path` of the client and signs the webview
+/// in to the proxy.
+#[tauri::command]
+pub fn get_client_url(path: Option<String>) -> Result<String, String> {
+    // Only paths on the proxy itself, never another origin.
+    let path = path
+        .filter(|path| {
+            path.starts_with('/')
+                && !path.starts_with("//")
+                && !path.contains('\\')
+                && !path.chars().any(char::is_control)
+        })
+        .unwrap_or_else(|| "/".to_string());
+    let code = crate::auth::generate().map_err(|err| err.to_string())?;
+    let mut guard = PROXY.lock().expect("PROXY mutex should not be poisoned");
+    let proxy = guard
+        .as_mut()
+        .ok_or_else(|| "The client proxy is not running".to_string())?;
+    if proxy.codes.len() >= MAX_CODES {
+        proxy.codes.clear();
+    }
+    proxy.codes.insert(code.clone(), path);
+    Ok(format!(
+        "http://{}{}?code={}",
+        proxy.addr, SESSION_PATH, code
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::{BufRead, BufReader};
+    use std::sync::mpsc;
+
+    #[test]
+    fn allows_only_the_client_routes() {
+        for path in [
+            "/",
+            "/index.html",
+            "/settings",
+            "/api/agents",
+            "/socket.io/",
+            "/assets/index-abc123.js",
+            "/chat/7f3c/room",
+        ] {
+            assert!(is_allowed(path), "{} should be allowed", path);
+        }
+        for path in [
+            "",
+            "/api",
+            "/apix/agents",
+            "/settingsx",
+            "/index.html/",
+            "//api/agents",
+            "/API/agents",
+            "/private/api/agents",
+        ] {
+            assert!(!is_allowed(path), "{} should be refused", path);
+        }
+    }
+
+    #[test]
+    fn refuses_paths_that_climb_out_of_a_route() {
+        for path in [
+            "/api/../admin",
+            "/api/..",
+            "/assets/%2e%2e/admin",
+            "/assets/%2E%2E/admin",
+            "/assets/.%2e/admin",
+            "/assets/..%2fadmin",
+            "/assets/..%2Fadmin",
+            "/assets/..%5cadmin",
+            "/assets/..\\admin",
+        ] {
+            assert!(!is_allowed(path), "{} should be refused", path);
+        }
+        // Dots that are not a whole segment are ordinary names.
+        assert!(is_allowed("/assets/..hidden"));
+        assert!(is_allowed("/assets/index..js"));
+    }
+
+    /// A server that records each request and answers by path: `/` is a
+    /// page, `/api/stream` sends one event, waits for the test, then sends
+    /// another; anything else echoes the request body.
+    fn upstream() -> (SocketAddr, mpsc::Receiver<String>, mpsc::Sender<()>) {
+        let listener = TcpListener::bind((Ipv4Addr::LOCALHOST, 0)).unwrap();
+        let addr = listener.local_addr().unwrap();
+        let (requests, received) = mpsc::channel();
+        let (release, released) = mpsc::channel::<()>();
+        thread::spawn(move || {
+            for stream in listener.incoming() {
+                let mut stream = stream.unwrap();
+                let (head, mut body) = read_head(&mut stream).unwrap().unwrap();
+                let head = String::from_utf8(head).unwrap();
+                let (_, length) = parse_head(head.as_bytes()).unwrap();
+                requests.send(head.clone()).unwrap();
+                let missing = length - body.len() as u64;
+                (&mut stream).take(missing).read_to_end(&mut body).unwrap();
+                if head.starts_with("GET / ") {
+                    stream
+                        .write_all(
+                            b"HTTP/1.1 200 OK\r\ncontent-type: text/html; charset=utf-8\r\n\
+                              content-length: 4\r\n\r\n<p/>",
+                        )
+                        .unwrap();
+                } else if head.starts_with("GET /api/stream ") {
+                    stream
+                        .write_all(
+                            b"HTTP/1.1 200 OK\r\ncontent-type: text/event-stream\r\n\
+                              transfer-encoding: chunked\r\n\r\n\
+                              b\r\ndata: one\n\n\r\n",
+                        )
+                        .unwrap();
+                    released.recv().unwrap();
+                    stream
+                        .write_all(b"b\r\ndata: two\n\n\r\n0\r\n\r\n")
+                        .unwrap();
+                } else {
+                    write!(
+                        stream,
+                        "HTTP/1.1 200 OK\r\ncontent-length: {}\r\n\r\n",
+                        body.len()
+                    )
+                    .unwrap();
+                    stream.write_all(&body).unwrap();
+                }
+            }
+        });
+        (addr, received, release)
+    }
+
+    /// Sends a raw request to the proxy and returns the response head and a
+    /// reader positioned at the body.
+    fn send(addr: SocketAddr, request: &str) -> (String, BufReader<TcpStream>) {
+        let mut stream = TcpStream::connect(addr).unwrap();
+        stream.write_all(request.as_bytes()).unwrap();
+        let mut reader = BufReader::new(stream);
+        let mut head = String::new();
+        loop {
+            let mut line = String::new();
+            reader.read_line(&mut line).unwrap();
+            if line == "\r\n" || line.is_empty() {
+                return (head, reader);
+            }
+            head.push_str(&line);
+        }
+    }
+
+    #[test]
+    fn forwards_signed_in_requests_and_streams_responses() {
+        let (upstream, requests, release) = upstream();
+        config::set(config::ServerConfig {
+            remote_url: Some(format!("http://{}", upstream)),
+            auth_token: Some("secret-token".into()),
+            ..Default::default()
+        });
+        start(Some(Csp::Policy(
+            "default-src 'self'; script-src 'self'".into(),
+        )))
+        .unwrap();
+        let link = Url::parse(&get_client_url(Some("/chat/agent".into())).unwrap()).unwrap();
+        let addr: SocketAddr = format!("127.0.0.1:{}", link.port().unwrap())
+            .parse()
+            .unwrap();
+        let target = format!("{}?{}", link.path(), link.query().unwrap());
+
+        // Another site rebound to 127.0.0.1 cannot use the link.
+        let (head, _) = send(
+            addr,
+            &format!("GET {} HTTP/1.1\r\nhost: evil.example\r\n\r\n", target),
+        );
+        assert!(head.starts_with("HTTP/1.1 403"), "{}", head);
+
+        let (head, _) = send(
+            addr,
+            &format!("GET {} HTTP/1.1\r\nhost: {}\r\n\r\n", target, addr),
+        );
+        assert!(head.starts_with("HTTP/1.1 303"), "{}", head);
+        assert!(head.contains("location: /chat/agent\r\n"), "{}", head);
+        let cookie = head
+            .lines()
+            .find_map(|line| line.strip_prefix("set-cookie: "))
+            .and_then(|cookie| cookie.split(';').next())
+            .unwrap()
+            .to_string();
+        assert!(cookie.starts_with("eliza-session="));
+        assert!(head.contains("HttpOnly"), "{}", head);
+
+        // The link works once.
+        let (head, _) = send(
+            addr,
+            &format!("GET {} HTTP/1.1\r\nhost: {}\r\n\r\n", target, addr),
+        );
+        assert!(head.starts_with("HTTP/1.1 403"), "{}", head);
+
+        // Without the cookie nothing reaches the server.
+        let (head, _) = send(
+            addr,
+            &format!("GET /api/agents HTTP/1.1\r\nhost: {}\r\n\r\n", addr),
+        );
+        assert!(head.starts_with("HTTP/1.1 403"), "{}", head);
+        let (head, _) = send(
+            addr,
+            &format!(
+                "GET /api/agents HTTP/1.1\r\nhost: {}\r\ncookie: eliza-session=guess\r\n\r\n",
+                addr
+            ),
+        );
+        assert!(head.starts_with("HTTP/1.1 403"), "{}", head);
+
+        // A socket.io long poll and the post that answers it.
+        let poll = "/socket.io/?EIO=4&transport=polling&t=abc";
+        let (head, mut body) = send(
+            addr,
+            &format!(
+                "POST {} HTTP/1.1\r\nhost: {}\r\ncookie: theme=dark; {}\r\n\
+                 x-api-key: from-the-page\r\ncontent-length: 2\r\n\r\n40",
+                poll, addr, cookie
+            ),
+        );
+        assert!(head.starts_with("HTTP/1.1 200"), "{}", head);
+        let mut echoed = String::new();
+        body.read_to_string(&mut echoed).unwrap();
+        assert_eq!(echoed, "40");
+        let seen = requests.recv().unwrap().to_ascii_lowercase();
+        assert!(
+            seen.starts_with(&format!("post {} http/1.1", poll.to_ascii_lowercase())),
+            "{}",
+            seen
+        );
+        assert!(seen.contains("x-api-key: secret-token\r\n"), "{}", seen);
+        assert!(!seen.contains("from-the-page"), "{}", seen);
+        assert!(seen.contains("cookie: theme=dark\r\n"), "{}", seen);
+        assert!(!seen.contains("eliza-session"), "{}", seen);
+
+        // Pages carry the CSP with the proxy's origin; other responses do not.
+        let (head, _) = send(
+            addr,
+            &format!(
+                "GET / HTTP/1.1\r\nhost: {}\r\ncookie: {}\r\n\r\n",
+                addr, cookie
+            ),
+        );
+        requests.recv().unwrap();
+        let policy = head
+            .lines()
+            .find_map(|line| line.strip_prefix("content-security-policy: "))
+            .unwrap_or_else(|| panic!("no CSP in {}", head));
+        assert!(policy.contains("default-src 'self'"), "{}", policy);
+        assert!(
+            policy.contains(&format!("script-src 'self' http://{}", addr)),
+            "{}",
+            policy
+        );
+        assert!(policy.contains(&format!("ws://{}", addr)), "{}", policy);
+        let (head, _) = send(
+            addr,
+            &format!(
+                "GET /api/agents HTTP/1.1\r\nhost: {}\r\ncookie: {}\r\n\r\n",
+                addr, cookie
+            ),
+        );
+        requests.recv().unwrap();
+        assert!(head.starts_with("HTTP/1.1 200"), "{}", head);
+        assert!(!head.contains("content-security-policy"), "{}", head);
+
+        // An upload reaches the server before the webview has sent all of it.
+        let mut upload = TcpStream::connect(addr).unwrap();
+        write!(
+            upload,
+            "POST /api/media HTTP/1.1\r\nhost: {}\r\ncookie: {}\r\ncontent-length: 6\r\n\r\nabc",
+            addr, cookie
+        )
+        .unwrap();
+        let seen = requests.recv_timeout(Duration::from_secs(5)).unwrap();
+        assert!(seen.contains("content-length: 6\r\n"), "{}", seen);
+        upload.write_all(b"def").unwrap();
+        let mut response = String::new();
+        upload.read_to_string(&mut response).unwrap();
+        assert!(response.starts_with("HTTP/1.1 200"), "{}", response);
+        assert!(response.ends_with("\r\n\r\nabcdef"), "{}", response);
+
+        // Server-sent events arrive as the server writes them.
+        let (head, mut body) = send(
+            addr,
+            &format!(
+                "GET /api/stream HTTP/1.1\r\nhost: {}\r\ncookie: {}\r\n\r\n",
+                addr, cookie
+            ),
+        );
+        assert!(head.starts_with("HTTP/1.1 200"), "{}", head);
+        assert!(head.contains("content-type: text/event-stream"), "{}", head);
+        let mut line = String::new();
+        body.read_line(&mut line).unwrap();
+        assert_eq!(line, "data: one\n");
+        release.send(()).unwrap();
+        let mut rest = String::new();
+        body.read_to_string(&mut rest).unwrap();
+        assert_eq!(rest, "\ndata: two\n\n");
+
+        // WebSocket upgrades are refused before reaching the server.
+        let (head, _) = send(
+            addr,
+            &format!(
+                "GET /socket.io/?EIO=4&transport=websocket HTTP/1.1\r\nhost: {}\r\n\
+                 cookie: {}\r\nupgrade: websocket\r\nconnection: Upgrade\r\n\r\n",
+                addr, cookie
+            ),
+        );
+        assert!(head.starts_with("HTTP/1.1 501"), "{}", head);
+    }
+}
@@ -0,0 +1,512 @@
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::RwLock;
+use std::time::Duration;
+use tauri::{AppHandle, LogicalSize, Manager};
+
+use crate::config::{self, ConfigError, ServerConfig};
+use crate::log_files::{self, RetentionPolicy};
+
+/// Version of the settings layout this build writes.
+pub const SCHEMA_VERSION: u64 = 1;
+
+/// `MIGRATIONS[n]` turns a version `n + 1` file into version `n + 2`, so a
+/// file from any earlier version is upgraded one step at a time. Append one
+/// whenever the layout changes and bump [`SCHEMA_VERSION`].
+const MIGRATIONS: &[fn(&mut Value)] = &[];
+
+pub const FILE_NAME: &str = "settings.json";
+
+/// Smallest window the layout works in, as in `tauri.conf.json`.
+const MIN_WINDOW_WIDTH: u32 = 800;
+const MIN_WINDOW_HEIGHT: u32 = 600;
+
+static SETTINGS: once_cell::sync::Lazy<RwLock<Store>> =
+    once_cell::sync::Lazy::new(|| RwLock::new(Store::default()));
+
+#[derive(Default)]
+struct Store {
+    path: Option<PathBuf>,
+    settings: Settings,
+    /// What was wrong with the file when it was loaded.
+    problems: Vec<ConfigError>,
+    /// The file was written by a newer app; saving over it would lose data.
+    newer_file: bool,
+    /// The settings the server was last started or connected with.
+    applied: Settings,
+}
+
+/// Everything the app reads from its settings file. Missing fields take
+/// their defaults, so a hand-written file only needs what it changes.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase", default)]
+pub struct Settings {
+    pub schema_version: u64,
+    pub server: ServerSettings,
+    /// Start the server when the app opens. When off, it waits for the
+    /// user to start it.
+    pub auto_start: bool,
+    pub log_retention: LogRetention,
+    pub remote: RemoteSettings,
+    pub window: WindowSettings,
+}
+
+/// The server the app spawns. `SERVER_HOST`, `SERVER_PORT`,
+/// `ELIZA_AUTO_PORT` and `ELIZA_LAUNCHER_PATH` still take precedence.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase", default)]
+pub struct ServerSettings {
+    pub host: String,
+    pub port: u16,
+    pub auto_port: bool,
+    pub launcher: Option<PathBuf>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase", default)]
+pub struct LogRetention {
+    pub max_file_mb: u64,
+    pub max_age_days: u64,
+    pub max_files: usize,
+}
+
+/// An Eliza server running elsewhere that the app connects to instead of
+/// spawning its own. `ELIZA_REMOTE_URL` takes precedence, and the token
+/// comes from `ELIZA_SERVER_AUTH_TOKEN`.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase", default)]
+pub struct RemoteSettings {
+    pub enabled: bool,
+    /// `http(s)://host[:port]` of the server.
+    pub url: Option<String>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase", default)]
+pub struct WindowSettings {
+    pub width: u32,
+    pub height: u32,
+    pub maximized: bool,
+}
+
+/// The settings as the webview sees them.
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SettingsView {
+    pub settings: Settings,
+    /// `None` when there is no config directory and nothing is saved.
+    pub path: Option<PathBuf>,
+    /// Problems with the file found at launch; the defaults are used instead.
+    pub problems: Vec<ConfigError>,
+    /// Server or remote settings changed and take effect when the server is
+    /// next started or restarted.
+    pub restart_required: bool,
+}
+
+impl Default for Settings {
+    fn default() -> Self {
+        Self {
+            schema_version: SCHEMA_VERSION,
+            server: ServerSettings::default(),
+            auto_start: true,
+            log_retention: LogRetention::default(),
+            remote: RemoteSettings::default(),
+            window: WindowSettings::default(),
+        }
+    }
+}
+
+impl Default for ServerSettings {
+    fn default() -> Self {
+        let config = ServerConfig::default();
+        Self {
+            host: config.host,
+            port: config.port,
+            auto_port: config.auto_port,
+            launcher: config.launcher,
+        }
+    }
+}
+
+impl Default for LogRetention {
+    fn default() -> Self {
+        let policy = RetentionPolicy::default();
+        Self {
+            max_file_mb: policy.max_file_bytes / (1024 * 1024),
+            max_age_days: policy.max_age.as_secs() / (24 * 60 * 60),
+            max_files: policy.max_files,
+        }
+    }
+}
+
+impl Default for WindowSettings {
+    fn default() -> Self {
+        Self {
+            width: 1200,
+            height: 800,
+            maximized: false,
+        }
+    }
+}
+
+impl Settings {
+    /// Every problem with the values, keyed by the field's path. The
+    /// filesystem is not consulted, so a settings file stays usable when,
+    /// say, the launcher it names is on a drive that is not mounted; the
+    /// launcher's absence is reported when the server is started.
+    pub fn validate(&self) -> Vec<ConfigError> {
+        let mut problems = Vec::new();
+        let mut problem =
+            |key: &'static str, message: String| problems.push(ConfigError { key, message });
+
+        if self.server.host.trim().is_empty() {
+            problem("server.host", "must not be empty".into());
+        }
+        if self.server.port == 0 {
+            problem("server.port", "must be between 1 and 65535".into());
+        }
+        if self.log_retention.max_file_mb == 0 {
+            problem("logRetention.maxFileMb", "must be at least 1".into());
+        }
+        if self.log_retention.max_age_days == 0 {
+            problem("logRetention.maxAgeDays", "must be at least 1".into());
+        }
+        if self.log_retention.max_files == 0 {
+            problem("logRetention.maxFiles", "must be at least 1".into());
+        }
+        match (&self.remote.url, self.remote.enabled) {
+            (Some(url), _) => {
+                if let Err(err) = config::validate_origin(url) {
+                    problem("remote.url", err.message);
+                }
+            }
+            (None, true) => problem("remote.url", "is required in remote mode".into()),
+            (None, false) => {}
+        }
+        if self.window.width < MIN_WINDOW_WIDTH {
+            problem(
+                "window.width",
+                format!("must be at least {}", MIN_WINDOW_WIDTH),
+            );
+        }
+        if self.window.height < MIN_WINDOW_HEIGHT {
+            problem(
+                "window.height",
+                format!("must be at least {}", MIN_WINDOW_HEIGHT),
+            );
+        }
+        problems
+    }
+
+    /// The server config these settings describe, before the environment
+    /// is applied.
+    pub fn server_config(&self) -> ServerConfig {
+        ServerConfig {
+            host: self.server.host.trim().to_string(),
+            port: self.server.port,
+            auto_port: self.server.auto_port,
+            launcher: self.server.launcher.clone(),
+            remote_url: self
+                .remote
+                .url
+                .as_deref()
+                .filter(|_| self.remote.enabled)
+                .and_then(|url| config::validate_origin(url).ok()),
+            auth_token: None,
+        }
+    }
+
+    pub fn retention_policy(&self) -> RetentionPolicy {
+        RetentionPolicy {
+            max_file_bytes: self.log_retention.max_file_mb * 1024 * 1024,
+            max_age: Duration::from_secs(self.log_retention.max_age_days * 24 * 60 * 60),
+            max_files: self.log_retention.max_files,
+        }
+    }
+}
+
+/// Loads the settings file at `path`, or the defaults when there is none.
+/// A file that cannot be read, migrated or validated is left untouched and
+/// the defaults are used; the problems are reported through
+/// [`get_settings`].
+pub fn init(path: Option<PathBuf>) {
+    let mut store = Store {
+        path,
+        ..Store::default()
+    };
+    match &store.path {
+        Some(path) => match load(path) {
+            Ok(Some(settings)) => {
+                println!("Loaded settings from {}", path.display());
+                store.settings = settings;
+            }
+            Ok(None) => {}
+            Err(problems) => {
+                for problem in &problems {
+                    eprintln!("Ignoring settings in {}: {}", path.display(), problem);
+                }
+                store.newer_file = problems.iter().any(|p| p.key == "schemaVersion");
+                store.problems = problems;
+            }
+        },
+        None => eprintln!("Settings will not be saved: no config directory"),
+    }
+    store.applied = store.settings.clone();
+    *SETTINGS
+        .write()
+        .expect("SETTINGS lock should not be poisoned") = store;
+}
+
+pub fn current() -> Settings {
+    SETTINGS
+        .read()
+        .expect("SETTINGS lock should not be poisoned")
+        .settings
+        .clone()
+}
+
+/// The server config to start with: the saved server settings with the
+/// environment applied on top. The settings count as applied from here on.
+pub fn server_config() -> Result<ServerConfig, ConfigError> {
+    let settings = current();
+    let config = settings.server_config().with_env()?;
+    SETTINGS
+        .write()
+        .expect("SETTINGS lock should not be poisoned")
+        .applied = settings;
+    Ok(config)
+}
+
+fn view() -> SettingsView {
+    let store = SETTINGS
+        .read()
+        .expect("SETTINGS lock should not be poisoned");
+    SettingsView {
+        settings: store.settings.clone(),
+        path: store.path.clone(),
+        problems: store.problems.clone(),
+        restart_required: store.settings.server != store.applied.server
+            || store.settings.remote != store.applied.remote,
+    }
+}
+
+/// `Ok(None)` when there is no file yet.
+fn load(path: &Path) -> Result<Option<Settings>, Vec<ConfigError>> {
+    let file_error = |message: String| {
+        vec![ConfigError {
+            key: "file",
+            message,
+        }]
+    };
+    let json = match fs::read_to_string(path) {
+        Ok(json) => json,
+        Err(err) if err.kind() == io::ErrorKind::NotFound => return Ok(None),
+        Err(err) => return Err(file_error(err.to_string())),
+    };
+    let mut value: Value =
+        serde_json::from_str(&json).map_err(|err| file_error(err.to_string()))?;
+    migrate(&mut value).map_err(|err| vec![err])?;
+    let settings: Settings =
+        serde_json::from_value(value).map_err(|err| file_error(err.to_string()))?;
+    let problems = settings.validate();
+    if problems.is_empty() {
+        Ok(Some(settings))
+    } else {
+        Err(problems)
+    }
+}
+
+/// Upgrades a settings file of any earlier schema to [`SCHEMA_VERSION`].
+/// A file without a version was written by hand and is taken to be current.
+fn migrate(value: &mut Value) -> Result<(), ConfigError> {
+    let invalid = |message: String| ConfigError {
+        key: "schemaVersion",
+        message,
+    };
+    let version = match value.get("schemaVersion") {
+        None => SCHEMA_VERSION,
+        Some(version) => version
+            .as_u64()
+            .filter(|version| *version >= 1)
+            .ok_or_else(|| invalid(format!("{} is not a schema version", version)))?,
+    };
+    if version > SCHEMA_VERSION {
+        return Err(invalid(format!(
+            "{} is newer than this app understands ({}); update the app",
+            version, SCHEMA_VERSION
+        )));
+    }
+    for migration in &MIGRATIONS[(version - 1) as usize..] {
+        migration(value);
+    }
+    if let Some(object) = value.as_object_mut() {
+        object.insert("schemaVersion".into(), SCHEMA_VERSION.into());
+    }
+    Ok(())
+}
+
+/// Writes through a temporary file so a crash never leaves half a file.
+fn save(path: &Path, settings: &Settings) -> io::Result<()> {
+    let json = serde_json::to_string_pretty(settings)
+        .map_err(|err| io::Error::new(io::ErrorKind::InvalidData, err))?;
+    if let Some(dir) = path.parent() {
+        fs::create_dir_all(dir)?;
+    }
+    let temp = path.with_extension("json.tmp");
+    fs::write(&temp, json)?;
+    fs::rename(&temp, path)
+}
+
+/// Validates and saves `settings`, then applies what can change while the
+/// app runs: log retention and the window. Server and remote settings are
+/// read when the server is next started.
+pub fn update(app: &AppHandle, mut settings: Settings) -> Result<SettingsView, Vec<ConfigError>> {
+    settings.schema_version = SCHEMA_VERSION;
+    let mut problems = settings.validate();
+    // Checked only here, when the user has just picked the path.
+    if let Some(launcher) = &settings.server.launcher {
+        if !launcher.is_file() {
+            problems.push(ConfigError {
+                key: "server.launcher",
+                message: format!("{} does not exist", launcher.display()),
+            });
+        }
+    }
+    if !problems.is_empty() {
+        return Err(problems);
+    }
+
+    let previous = {
+        let mut store = SETTINGS
+            .write()
+            .expect("SETTINGS lock should not be poisoned");
+        if store.newer_file {
+            return Err(vec![ConfigError {
+                key: "schemaVersion",
+                message: "the settings file was written by a newer version of the app".into(),
+            }]);
+        }
+        if let Some(path) = &store.path {
+            save(path, &settings).map_err(|err| {
+                vec![ConfigError {
+                    key: "file",
+                    message: format!("Failed to save {}: {}", path.display(), err),
+                }]
+            })?;
+        }
+        store.problems.clear();
+        std::mem::replace(&mut store.settings, settings.clone())
+    };
+
+    if settings.log_retention != previous.log_retention {
+        log_files::set_policy(settings.retention_policy());
+    }
+    if settings.window != previous.window {
+        apply_window(app, &settings.window);
+    }
+    Ok(view())
+}
+
+fn apply_window(app: &AppHandle, window: &WindowSettings) {
+    let Some(main_window) = app.get_webview_window("main") else {
+        return;
+    };
+    let result = if window.maximized {
+        main_window.maximize()
+    } else {
+        main_window
+            .unmaximize()
+            .and_then(|()| main_window.set_size(LogicalSize::new(window.width, window.height)))
+    };
+    if let Err(err) = result {
+        eprintln!("Failed to apply window settings: {}", err);
+    }
+}
+
+/// The settings in effect and any problems loading them.
+#[tauri::command]
+pub fn get_settings() -> SettingsView {
+    view()
+}
+
+/// Saves new settings. Invalid values are rejected as a whole, with one
+/// error per field.
+#[tauri::command]
+pub fn update_settings(
+    app: AppHandle,
+    settings: Settings,
+) -> Result<SettingsView, Vec<ConfigError>> {
+    update(&app, settings)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn migrates_files_up_to_the_current_schema() {
+        let mut value = json!({ "autoStart": false });
+        migrate(&mut value).unwrap();
+        assert_eq!(value["schemaVersion"], SCHEMA_VERSION);
+        assert_eq!(value["autoStart"], false);
+
+        let mut value = json!({ "schemaVersion": 1, "autoStart": false });
+        migrate(&mut value).unwrap();
+        assert_eq!(value["schemaVersion"], SCHEMA_VERSION);
+        assert_eq!(value["autoStart"], false);
+    }
+
+    #[test]
+    fn refuses_a_file_from_a_newer_app() {
+        let mut value = json!({ "schemaVersion": SCHEMA_VERSION + 1, "autoStart": false });
+        let err = migrate(&mut value).unwrap_err();
+        assert_eq!(err.key, "schemaVersion");
+        assert!(err.message.contains("newer"), "{}", err.message);
+        // The file is left as it was, so nothing a newer app wrote is lost.
+        assert_eq!(value["schemaVersion"], SCHEMA_VERSION + 1);
+
+        let mut value = json!({ "schemaVersion": u64::MAX });
+        assert!(migrate(&mut value).is_err());
+    }
+
+    #[test]
+    fn refuses_an_unknown_schema_version() {
+        for version in [
+            json!(0),
+            json!(-1),
+            json!(1.5),
+            json!("1"),
+            json!(null),
+            json!([1]),
+        ] {
+            let mut value = json!({ "schemaVersion": version });
+            let err = migrate(&mut value).unwrap_err();
+            assert_eq!(err.key, "schemaVersion");
+            assert!(
+                err.message.contains("is not a schema version"),
+                "{}",
+                err.message
+            );
+        }
+    }
+
+    #[test]
+    fn loads_a_file_naming_a_missing_launcher() {
+        let dir = std::env::temp_dir().join(format!("eliza-settings-test-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        let path = dir.join(FILE_NAME);
+        let launcher = dir.join("missing").join("eliza");
+        let json = json!({ "autoStart": false, "server": { "launcher": launcher } });
+        fs::write(&path, json.to_string()).unwrap();
+
+        let settings = load(&path).unwrap().unwrap();
+        assert!(!settings.auto_start);
+        assert_eq!(settings.server.launcher, Some(launcher));
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,71 @@
+use std::io;
+use std::sync::atomic::{AtomicI32, Ordering};
+use std::thread;
+use std::time::Duration;
+
+const POLL_INTERVAL: Duration = Duration::from_millis(100);
+
+/// The last SIGINT or SIGTERM received, or 0.
+static RECEIVED: AtomicI32 = AtomicI32::new(0);
+
+/// Records SIGINT and SIGTERM instead of letting them kill the process, so
+/// they can go through the same graceful shutdown as quitting the app. A
+/// second signal while that shutdown runs exits at once, so a hung shutdown
+/// can still be interrupted.
+#[cfg(unix)]
+pub fn install() -> io::Result<()> {
+    extern "C" fn on_signal(signal: libc::c_int) {
+        // Only an atomic swap and _exit: nothing else is async-signal-safe
+        // here.
+        if RECEIVED.swap(signal, Ordering::SeqCst) != 0 {
+            // SAFETY: _exit is async-signal-safe and does not return.
+            unsafe { libc::_exit(128 + signal) };
+        }
+    }
+
+    for signal in [libc::SIGINT, libc::SIGTERM] {
+        // SAFETY: `action` is fully initialised before use and the handler
+        // only calls async-signal-safe functions.
+        unsafe {
+            let mut action: libc::sigaction = std::mem::zeroed();
+            action.sa_sigaction = on_signal as extern "C" fn(libc::c_int) as libc::sighandler_t;
+            // Restarts calls such as accept that the signal interrupts,
+            // instead of failing them with EINTR.
+            action.sa_flags = libc::SA_RESTART;
+            libc::sigemptyset(&mut action.sa_mask);
+            if libc::sigaction(signal, &action, std::ptr::null_mut()) == -1 {
+                return Err(io::Error::last_os_error());
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Ctrl+C keeps its default behaviour on Windows.
+#[cfg(windows)]
+pub fn install() -> io::Result<()> {
+    Ok(())
+}
+
+/// The signal that asked the app to shut down, if any.
+pub fn received() -> Option<i32> {
+    match RECEIVED.load(Ordering::SeqCst) {
+        0 => None,
+        signal => Some(signal),
+    }
+}
+
+/// Installs the handlers and calls `on_signal` from a background thread once
+/// the first SIGINT or SIGTERM arrives.
+pub fn watch(on_signal: impl FnOnce(i32) + Send + 'static) -> io::Result<()> {
+    install()?;
+    thread::Builder::new()
+        .name("eliza-signals".into())
+        .spawn(move || loop {
+            if let Some(signal) = received() {
+                return on_signal(signal);
+            }
+            thread::sleep(POLL_INTERVAL);
+        })?;
+    Ok(())
+}
@@ -0,0 +1,73 @@
+use serde::Serialize;
+use std::sync::Mutex;
+
+use crate::error::ErrorReport;
+use crate::host::Host;
+
+/// Event name carrying every [`ServerState`] transition.
+pub const STATE_EVENT: &str = "server-state";
+
+static SERVER_STATE: once_cell::sync::Lazy<Mutex<ServerState>> =
+    once_cell::sync::Lazy::new(|| Mutex::new(ServerState::Idle));
+
+/// Lifecycle of the Eliza server as seen by the app:
+/// Idle → Spawning → WaitingForHealth → Ready → Crashed → Stopped.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+#[serde(
+    tag = "state",
+    rename_all = "camelCase",
+    rename_all_fields = "camelCase"
+)]
+pub enum ServerState {
+    Idle,
+    Spawning {
+        attempt: u32,
+    },
+    WaitingForHealth {
+        /// `None` when waiting on a server the app did not spawn.
+        pid: Option<u32>,
+    },
+    Ready {
+        version: String,
+        agents_ready: bool,
+        /// The server was already running and was not spawned by the app.
+        external: bool,
+    },
+    Crashed {
+        error: ErrorReport,
+        will_restart: bool,
+    },
+    Stopped,
+}
+
+pub fn current() -> ServerState {
+    SERVER_STATE
+        .lock()
+        .expect("SERVER_STATE mutex should not be poisoned")
+        .clone()
+}
+
+/// Records a transition and pushes it to the webview. The main window
+/// leaves the client when the server stops being usable.
+pub fn set(host: &Host, state: ServerState) {
+    {
+        let mut guard = SERVER_STATE
+            .lock()
+            .expect("SERVER_STATE mutex should not be poisoned");
+        if *guard == state {
+            return;
+        }
+        *guard = state.clone();
+    }
+    if let Err(err) = host.emit(STATE_EVENT, &state) {
+        eprintln!("Failed to emit {} event: {}", STATE_EVENT, err);
+    }
+    if let Some(app) = host.app() {
+        crate::proxy::follow_state(app, &state);
+    }
+}
+
+#[tauri::command]
+pub fn get_server_state() -> ServerState {
+    current()
+}
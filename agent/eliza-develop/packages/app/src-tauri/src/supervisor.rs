@@ -0,0 +1,677 @@
+use serde::Serialize;
+use std::collections::VecDeque;
+use std::fmt;
+use std::process::ExitStatus;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Mutex, MutexGuard};
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant};
+
+use crate::error::{AppError, ErrorReport};
+use crate::health::{self, ProbeResult};
+use crate::host::Host;
+use crate::process::ShutdownOutcome;
+use crate::state::{self, ServerState};
+use crate::{config, SERVER_PROCESS};
+
+/// Event name the frontend listens on for server lifecycle changes.
+pub const LIFECYCLE_EVENT: &str = "server-lifecycle";
+
+const EXIT_POLL_INTERVAL: Duration = Duration::from_millis(250);
+const HEALTH_POLL_INTERVAL: Duration = Duration::from_secs(1);
+/// How often a remote server is probed once it is up.
+const MONITOR_INTERVAL: Duration = Duration::from_secs(5);
+const SLEEP_SLICE: Duration = Duration::from_millis(100);
+
+/// Number of [`CrashRecord`]s kept for diagnostics.
+const CRASH_HISTORY: usize = 20;
+
+static STOP_REQUESTED: AtomicBool = AtomicBool::new(false);
+
+/// Held for the whole of a start, stop or restart so that requests from the
+/// UI run one after another instead of racing each other.
+static CONTROL: Mutex<()> = Mutex::new(());
+
+/// How the most recently supervised server ended.
+static LAST_EXIT: Mutex<Option<ExitKind>> = Mutex::new(None);
+
+/// The most recent failed runs, oldest first.
+static CRASH_RECORDS: Mutex<VecDeque<CrashRecord>> = Mutex::new(VecDeque::new());
+
+/// The supervisor or external-wait thread, if one was started.
+static SUPERVISOR: once_cell::sync::Lazy<Mutex<Option<JoinHandle<()>>>> =
+    once_cell::sync::Lazy::new(|| Mutex::new(None));
+
+/// Tells the supervisor that the server is being stopped on purpose, so the
+/// next exit is not treated as a crash.
+pub fn request_stop() {
+    STOP_REQUESTED.store(true, Ordering::SeqCst);
+}
+
+pub fn stop_requested() -> bool {
+    STOP_REQUESTED.load(Ordering::SeqCst)
+}
+
+/// Serializes control requests; see [`CONTROL`].
+pub fn control() -> MutexGuard<'static, ()> {
+    CONTROL
+        .lock()
+        .expect("CONTROL mutex should not be poisoned")
+}
+
+pub fn last_exit() -> Option<ExitKind> {
+    *LAST_EXIT
+        .lock()
+        .expect("LAST_EXIT mutex should not be poisoned")
+}
+
+/// Whether a supervisor thread is still watching the server.
+pub fn is_active() -> bool {
+    SUPERVISOR
+        .lock()
+        .expect("SUPERVISOR mutex should not be poisoned")
+        .as_ref()
+        .is_some_and(|handle| !handle.is_finished())
+}
+
+/// Stops the server and waits for the supervisor thread to finish, so a new
+/// one can be started without two of them spawning servers.
+pub fn stop() -> ShutdownOutcome {
+    let outcome = crate::shutdown_server();
+    let handle = SUPERVISOR
+        .lock()
+        .expect("SUPERVISOR mutex should not be poisoned")
+        .take();
+    if let Some(handle) = handle {
+        if handle.join().is_err() {
+            eprintln!("Eliza supervisor thread panicked");
+        }
+    }
+    // The supervisor may have spawned a server just before it saw the stop.
+    match crate::shutdown_server() {
+        ShutdownOutcome::NotRunning => outcome,
+        late => late,
+    }
+}
+
+/// How the server process ended.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(
+    tag = "kind",
+    rename_all = "camelCase",
+    rename_all_fields = "camelCase"
+)]
+pub enum ExitKind {
+    Clean,
+    Signal { signal: i32 },
+    Code { code: i32 },
+}
+
+impl ExitKind {
+    pub fn classify(status: ExitStatus) -> Self {
+        if status.success() {
+            return ExitKind::Clean;
+        }
+        #[cfg(unix)]
+        {
+            use std::os::unix::process::ExitStatusExt;
+            if let Some(signal) = status.signal() {
+                return ExitKind::Signal { signal };
+            }
+        }
+        ExitKind::Code {
+            code: status.code().unwrap_or(-1),
+        }
+    }
+
+    pub fn is_crash(&self) -> bool {
+        !matches!(self, ExitKind::Clean)
+    }
+}
+
+impl fmt::Display for ExitKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ExitKind::Clean => write!(f, "exited cleanly"),
+            ExitKind::Signal { signal } => write!(f, "killed by signal {}", signal),
+            ExitKind::Code { code } => write!(f, "exited with code {}", code),
+        }
+    }
+}
+
+/// A run of the server that crashed, timed out or failed to spawn.
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct CrashRecord {
+    /// Unix time in milliseconds.
+    pub time: u64,
+    pub attempt: u32,
+    pub uptime_ms: u64,
+    pub error: ErrorReport,
+}
+
+pub fn crash_records() -> Vec<CrashRecord> {
+    CRASH_RECORDS
+        .lock()
+        .expect("CRASH_RECORDS mutex should not be poisoned")
+        .iter()
+        .cloned()
+        .collect()
+}
+
+fn record_crash(attempt: u32, uptime: Duration, error: &AppError) {
+    let mut records = CRASH_RECORDS
+        .lock()
+        .expect("CRASH_RECORDS mutex should not be poisoned");
+    if records.len() == CRASH_HISTORY {
+        records.pop_front();
+    }
+    records.push_back(CrashRecord {
+        time: crate::logs::now_millis(),
+        attempt,
+        uptime_ms: uptime.as_millis() as u64,
+        error: error.report(),
+    });
+}
+
+/// Payload of [`LIFECYCLE_EVENT`].
+#[derive(Debug, Clone, Serialize)]
+#[serde(
+    tag = "type",
+    rename_all = "camelCase",
+    rename_all_fields = "camelCase"
+)]
+pub enum LifecycleEvent {
+    Started { pid: u32, attempt: u32 },
+    SpawnFailed { message: String, attempt: u32 },
+    Exited { exit: ExitKind, uptime_ms: u64 },
+    Restarting { attempt: u32, delay_ms: u64 },
+    CrashLoop { crashes: usize, window_secs: u64 },
+    Stopped,
+}
+
+/// Restart behaviour for a crashed server.
+#[derive(Debug, Clone)]
+pub struct RestartPolicy {
+    /// Delay before the first restart; doubled after every consecutive crash.
+    pub initial_backoff: Duration,
+    pub max_backoff: Duration,
+    /// A run that stays up this long resets the backoff.
+    pub stable_after: Duration,
+    /// Give up once this many crashes happen within `crash_window`.
+    pub max_crashes: usize,
+    pub crash_window: Duration,
+    /// How long a freshly spawned server may take to pass the health probe.
+    pub health_timeout: Duration,
+}
+
+impl Default for RestartPolicy {
+    fn default() -> Self {
+        Self {
+            initial_backoff: Duration::from_secs(1),
+            max_backoff: Duration::from_secs(60),
+            stable_after: Duration::from_secs(120),
+            max_crashes: 5,
+            crash_window: Duration::from_secs(300),
+            health_timeout: Duration::from_secs(120),
+        }
+    }
+}
+
+impl RestartPolicy {
+    /// Backoff for the `failures`-th consecutive failure (1-based).
+    pub fn backoff(&self, failures: u32) -> Duration {
+        let factor = 1u32 << failures.saturating_sub(1).min(16);
+        self.initial_backoff
+            .saturating_mul(factor)
+            .min(self.max_backoff)
+    }
+
+    /// Adds a crash at `now` to `crashes`, forgets those older than
+    /// `crash_window`, and tells whether the rest add up to a crash loop.
+    fn crash_loop(&self, crashes: &mut VecDeque<Instant>, now: Instant) -> bool {
+        crashes.push_back(now);
+        while crashes
+            .front()
+            .is_some_and(|t| now.duration_since(*t) > self.crash_window)
+        {
+            crashes.pop_front();
+        }
+        crashes.len() >= self.max_crashes
+    }
+}
+
+/// Starts the supervisor thread. It spawns the server, waits for it to exit
+/// and restarts it on crashes until a stop is requested or the crash-loop
+/// limit is reached.
+pub fn start(host: Host, policy: RestartPolicy) {
+    STOP_REQUESTED.store(false, Ordering::SeqCst);
+    spawn_thread("eliza-supervisor", move || supervise(&host, &policy));
+}
+
+fn supervise(host: &Host, policy: &RestartPolicy) {
+    let mut crashes: VecDeque<Instant> = VecDeque::new();
+    let mut failures = 0u32;
+    let mut attempt = 0u32;
+
+    let report = crate::preflight::run();
+    for check in &report.checks {
+        println!(
+            "Preflight {:?}: {:?} {}",
+            check.name, check.status, check.message
+        );
+    }
+    if !report.passed {
+        let failures = report.failures().map(|check| check.message.clone());
+        return give_up(
+            host,
+            AppError::PreflightFailed {
+                failures: failures.collect(),
+            },
+        );
+    }
+
+    while !stop_requested() {
+        let started_at = Instant::now();
+        state::set(host, ServerState::Spawning { attempt });
+        let error = match crate::spawn_server(host) {
+            Ok(pid) => {
+                emit(host, LifecycleEvent::Started { pid, attempt });
+                state::set(host, ServerState::WaitingForHealth { pid: Some(pid) });
+                match wait_for_exit(host, policy.health_timeout) {
+                    RunEnd::Stopped => break,
+                    RunEnd::HealthTimeout => {
+                        eprintln!(
+                            "Eliza server did not become healthy within {:?}",
+                            policy.health_timeout
+                        );
+                        stop_unhealthy();
+                        AppError::HealthTimeout {
+                            timeout_secs: policy.health_timeout.as_secs(),
+                        }
+                    }
+                    RunEnd::Exited(status) => {
+                        let exit = ExitKind::classify(status);
+                        let uptime = started_at.elapsed();
+                        *LAST_EXIT
+                            .lock()
+                            .expect("LAST_EXIT mutex should not be poisoned") = Some(exit);
+                        if let Err(err) = crate::process::kill_group(pid) {
+                            eprintln!("Failed to clean up Eliza server process group: {}", err);
+                        }
+                        println!("Eliza server exited: {:?} after {:?}", exit, uptime);
+                        emit(
+                            host,
+                            LifecycleEvent::Exited {
+                                exit,
+                                uptime_ms: uptime.as_millis() as u64,
+                            },
+                        );
+                        if stop_requested() || !exit.is_crash() {
+                            break;
+                        }
+                        if uptime >= policy.stable_after {
+                            failures = 0;
+                        }
+                        AppError::ServerExited { exit }
+                    }
+                }
+            }
+            Err(err) => {
+                eprintln!("{}", err);
+                emit(
+                    host,
+                    LifecycleEvent::SpawnFailed {
+                        message: err.to_string(),
+                        attempt,
+                    },
+                );
+                err
+            }
+        };
+
+        record_crash(attempt, started_at.elapsed(), &error);
+        if !error.is_retryable() {
+            return give_up(host, error);
+        }
+
+        if policy.crash_loop(&mut crashes, Instant::now()) {
+            eprintln!(
+                "Eliza server crashed {} times in {:?}, giving up",
+                crashes.len(),
+                policy.crash_window
+            );
+            emit(
+                host,
+                LifecycleEvent::CrashLoop {
+                    crashes: crashes.len(),
+                    window_secs: policy.crash_window.as_secs(),
+                },
+            );
+            return give_up(
+                host,
+                AppError::CrashLoop {
+                    crashes: crashes.len(),
+                    window_secs: policy.crash_window.as_secs(),
+                },
+            );
+        }
+
+        failures += 1;
+        attempt += 1;
+        let delay = policy.backoff(failures);
+        println!(
+            "Restarting Eliza server in {:?} (attempt {})",
+            delay, attempt
+        );
+        emit(
+            host,
+            LifecycleEvent::Restarting {
+                attempt,
+                delay_ms: delay.as_millis() as u64,
+            },
+        );
+        state::set(
+            host,
+            ServerState::Crashed {
+                error: error.report(),
+                will_restart: true,
+            },
+        );
+        if !sleep_unless_stopped(delay) {
+            break;
+        }
+    }
+
+    emit(host, LifecycleEvent::Stopped);
+    state::set(host, ServerState::Stopped);
+}
+
+fn give_up(host: &Host, error: AppError) {
+    state::set(
+        host,
+        ServerState::Crashed {
+            error: error.report(),
+            will_restart: false,
+        },
+    );
+}
+
+/// Waits for a server that was already starting when the app launched. It
+/// is spawned and supervised by us only if it goes away before it is healthy.
+pub fn await_external(host: Host, policy: RestartPolicy) {
+    STOP_REQUESTED.store(false, Ordering::SeqCst);
+    spawn_thread("eliza-external-wait", move || {
+        state::set(&host, ServerState::WaitingForHealth { pid: None });
+        let deadline = Instant::now() + policy.health_timeout;
+        loop {
+            let config = config::current();
+            match health::probe(&config.api_url()) {
+                ProbeResult::Starting if Instant::now() >= deadline => {
+                    return give_up(
+                        &host,
+                        AppError::HealthTimeout {
+                            timeout_secs: policy.health_timeout.as_secs(),
+                        },
+                    )
+                }
+                ProbeResult::Starting => {}
+                ProbeResult::NotRunning => return supervise(&host, &policy),
+                ProbeResult::Healthy {
+                    version,
+                    agents_ready,
+                } => {
+                    return state::set(
+                        &host,
+                        ServerState::Ready {
+                            version,
+                            agents_ready,
+                            external: true,
+                        },
+                    )
+                }
+                ProbeResult::Unauthorized => {
+                    return give_up(
+                        &host,
+                        AppError::Unauthorized {
+                            url: config.api_url(),
+                        },
+                    )
+                }
+                ProbeResult::Foreign { reason } => {
+                    return give_up(
+                        &host,
+                        AppError::PortConflict {
+                            port: config.port,
+                            reason,
+                        },
+                    )
+                }
+            }
+            if !sleep_unless_stopped(HEALTH_POLL_INTERVAL) {
+                return;
+            }
+        }
+    });
+}
+
+/// Watches a remote server without ever spawning one. The state follows
+/// its health probe, starting from `first`, the probe startup already made;
+/// an outage shows as a crash that clears once the server answers again.
+pub fn monitor(host: Host, first: ProbeResult) {
+    STOP_REQUESTED.store(false, Ordering::SeqCst);
+    spawn_thread("eliza-monitor", move || {
+        let url = config::current().api_url();
+        println!("Monitoring remote Eliza server at {}", url);
+        let mut first = Some(first);
+        loop {
+            let probe = first.take().unwrap_or_else(|| health::probe(&url));
+            let interval = match probe {
+                ProbeResult::Healthy {
+                    version,
+                    agents_ready,
+                } => {
+                    state::set(
+                        &host,
+                        ServerState::Ready {
+                            version,
+                            agents_ready,
+                            external: true,
+                        },
+                    );
+                    if agents_ready {
+                        MONITOR_INTERVAL
+                    } else {
+                        HEALTH_POLL_INTERVAL
+                    }
+                }
+                ProbeResult::Starting => {
+                    state::set(&host, ServerState::WaitingForHealth { pid: None });
+                    HEALTH_POLL_INTERVAL
+                }
+                // The token is read at launch, so asking again will not help.
+                ProbeResult::Unauthorized => {
+                    return give_up(&host, AppError::Unauthorized { url });
+                }
+                ProbeResult::NotRunning => {
+                    unreachable(&host, &url, "the server is not reachable".into())
+                }
+                ProbeResult::Foreign { reason } => unreachable(&host, &url, reason),
+            };
+            if !sleep_unless_stopped(interval) {
+                return;
+            }
+        }
+    });
+}
+
+fn unreachable(host: &Host, url: &str, reason: String) -> Duration {
+    let error = AppError::RemoteUnavailable {
+        url: url.to_string(),
+        reason,
+    };
+    if !matches!(state::current(), ServerState::Crashed { .. }) {
+        eprintln!("{}", error);
+    }
+    state::set(
+        host,
+        ServerState::Crashed {
+            error: error.report(),
+            will_restart: true,
+        },
+    );
+    MONITOR_INTERVAL
+}
+
+/// How a supervised run ended.
+enum RunEnd {
+    Exited(ExitStatus),
+    /// The server never answered the health probe in time.
+    HealthTimeout,
+    /// A stop was requested, or the child was taken out of `SERVER_PROCESS`
+    /// by a shutdown.
+    Stopped,
+}
+
+/// Polls the current child until it exits, moving the state to `Ready` once
+/// the health probe succeeds and probing until its agents are loaded.
+fn wait_for_exit(host: &Host, health_timeout: Duration) -> RunEnd {
+    let deadline = Instant::now() + health_timeout;
+    let mut healthy = false;
+    let mut agents_loaded = false;
+    let mut next_probe = Instant::now();
+    loop {
+        {
+            let mut guard = SERVER_PROCESS
+                .lock()
+                .expect("SERVER_PROCESS mutex should not be poisoned");
+            let Some(child) = guard.as_mut() else {
+                return RunEnd::Stopped;
+            };
+            if stop_requested() {
+                return RunEnd::Stopped;
+            }
+            match child.try_wait() {
+                Ok(Some(status)) => {
+                    *guard = None;
+                    return RunEnd::Exited(status);
+                }
+                Ok(None) => {}
+                Err(err) => eprintln!("Failed to poll Eliza server process: {}", err),
+            }
+        }
+        if !agents_loaded && Instant::now() >= next_probe {
+            if let ProbeResult::Healthy {
+                version,
+                agents_ready,
+            } = health::probe(&config::current().api_url())
+            {
+                healthy = true;
+                agents_loaded = agents_ready;
+                state::set(
+                    host,
+                    ServerState::Ready {
+                        version,
+                        agents_ready,
+                        external: false,
+                    },
+                );
+            }
+            next_probe = Instant::now() + HEALTH_POLL_INTERVAL;
+        }
+        if !healthy && Instant::now() >= deadline {
+            return RunEnd::HealthTimeout;
+        }
+        thread::sleep(EXIT_POLL_INTERVAL);
+    }
+}
+
+/// Stops a server that never became healthy so it can be started again.
+fn stop_unhealthy() {
+    let child = SERVER_PROCESS
+        .lock()
+        .expect("SERVER_PROCESS mutex should not be poisoned")
+        .take();
+    if let Some(mut child) = child {
+        let outcome = crate::process::terminate(&mut child, crate::process::shutdown_grace());
+        println!("Stopped unhealthy Eliza server: {:?}", outcome);
+    }
+}
+
+/// Sleeps for `duration`, waking early if a stop is requested. Returns
+/// `false` when interrupted.
+fn sleep_unless_stopped(duration: Duration) -> bool {
+    let deadline = Instant::now() + duration;
+    while Instant::now() < deadline {
+        if stop_requested() {
+            return false;
+        }
+        thread::sleep(SLEEP_SLICE.min(deadline.saturating_duration_since(Instant::now())));
+    }
+    !stop_requested()
+}
+
+fn spawn_thread(name: &str, f: impl FnOnce() + Send + 'static) {
+    match thread::Builder::new().name(name.into()).spawn(f) {
+        Ok(handle) => {
+            *SUPERVISOR
+                .lock()
+                .expect("SUPERVISOR mutex should not be poisoned") = Some(handle);
+        }
+        Err(err) => eprintln!("Failed to spawn {} thread: {}", name, err),
+    }
+}
+
+fn emit(host: &Host, event: LifecycleEvent) {
+    if let Err(err) = host.emit(LIFECYCLE_EVENT, &event) {
+        eprintln!("Failed to emit {} event: {}", LIFECYCLE_EVENT, err);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn doubles_the_backoff_up_to_the_cap() {
+        let policy = RestartPolicy::default();
+        assert_eq!(policy.backoff(0), Duration::from_secs(1));
+        assert_eq!(policy.backoff(1), Duration::from_secs(1));
+        assert_eq!(policy.backoff(2), Duration::from_secs(2));
+        assert_eq!(policy.backoff(6), Duration::from_secs(32));
+        assert_eq!(policy.backoff(7), Duration::from_secs(60));
+        assert_eq!(policy.backoff(u32::MAX), Duration::from_secs(60));
+
+        // Neither the shift nor the multiplication can overflow.
+        let policy = RestartPolicy {
+            initial_backoff: Duration::MAX,
+            ..RestartPolicy::default()
+        };
+        assert_eq!(policy.backoff(u32::MAX), policy.max_backoff);
+    }
+
+    #[test]
+    fn counts_only_crashes_within_the_window() {
+        let policy = RestartPolicy {
+            max_crashes: 3,
+            crash_window: Duration::from_secs(60),
+            ..RestartPolicy::default()
+        };
+        let start = Instant::now();
+        let at = |secs| start + Duration::from_secs(secs);
+        let mut crashes = VecDeque::new();
+
+        assert!(!policy.crash_loop(&mut crashes, at(0)));
+        assert!(!policy.crash_loop(&mut crashes, at(30)));
+        // The first crash has left the window, so this is only the second.
+        assert!(!policy.crash_loop(&mut crashes, at(61)));
+        assert_eq!(crashes.len(), 2);
+        // A crash exactly at the edge of the window still counts.
+        assert!(policy.crash_loop(&mut crashes, at(90)));
+        assert_eq!(crashes.len(), 3);
+
+        // A long quiet spell forgets them all.
+        assert!(!policy.crash_loop(&mut crashes, at(1000)));
+        assert_eq!(crashes.len(), 1);
+    }
+}
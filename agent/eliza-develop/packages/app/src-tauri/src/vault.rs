@@ -0,0 +1,615 @@
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+
+use argon2::{Algorithm, Argon2, Params, Version};
+use chacha20poly1305::aead::{Aead, KeyInit, Payload};
+use chacha20poly1305::{XChaCha20Poly1305, XNonce};
+use serde::{Deserialize, Serialize};
+use zeroize::{Zeroize, ZeroizeOnDrop, Zeroizing};
+
+use crate::{config, paths};
+
+/// Name of the encrypted secrets file in the app data dir.
+pub const FILE_NAME: &str = "secrets.vault";
+
+/// Unlocks the vault at launch without a prompt, e.g. for headless use.
+/// It is never passed on to the server.
+pub const PASSPHRASE_ENV: &str = "ELIZA_VAULT_PASSPHRASE";
+
+const FORMAT_VERSION: u32 = 1;
+const MIN_PASSPHRASE_CHARS: usize = 8;
+const KEY_BYTES: usize = 32;
+const SALT_BYTES: usize = 16;
+const NONCE_BYTES: usize = 24;
+
+/// Binds the ciphertext to this file format.
+const ASSOCIATED_DATA: &[u8] = b"eliza-vault:1";
+
+/// Variables a secret must not replace: the ones the app sets for the
+/// server itself, and ones that change which code the server runs.
+const RESERVED_NAMES: &[&str] = &[
+    config::PORT_ENV,
+    config::HOST_ENV,
+    config::AUTH_TOKEN_ENV,
+    "LOG_JSON_FORMAT",
+    "PATH",
+    "LD_PRELOAD",
+    "LD_LIBRARY_PATH",
+    "LD_AUDIT",
+    "NODE_OPTIONS",
+    "NODE_PATH",
+    "BUN_OPTIONS",
+];
+
+/// Prefixes of reserved names: the server's own settings, the vault's, and
+/// the macOS dynamic linker's.
+const RESERVED_PREFIXES: &[&str] = &["ELIZA_SERVER_", "ELIZA_VAULT_", "DYLD_"];
+
+/// Bounds on the key parameters read from a vault file, so a doctored file
+/// can neither weaken the key nor make deriving it exhaust memory or time.
+/// The lower bounds are the cost new vaults are created with.
+const MIN_MEMORY_KIB: u32 = 19 * 1024;
+const MAX_MEMORY_KIB: u32 = 1024 * 1024;
+const MIN_ITERATIONS: u32 = 2;
+const MAX_ITERATIONS: u32 = 16;
+const MAX_PARALLELISM: u32 = 16;
+
+/// Argon2id cost, stored with the file so it can be raised for new vaults
+/// without breaking old ones. The defaults are OWASP's minimum.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct KdfParams {
+    memory_kib: u32,
+    iterations: u32,
+    parallelism: u32,
+    salt: String,
+}
+
+/// The file on disk. Only the secrets are encrypted; the rest is needed to
+/// derive the key and open them.
+#[derive(Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct VaultFile {
+    version: u32,
+    kdf: KdfParams,
+    nonce: String,
+    ciphertext: String,
+}
+
+#[derive(Serialize, Deserialize, Zeroize, ZeroizeOnDrop)]
+#[serde(rename_all = "camelCase")]
+struct StoredSecret {
+    value: String,
+    /// Whether the secret is set in the server's environment at launch.
+    inject: bool,
+}
+
+struct Unlocked {
+    key: Zeroizing<[u8; KEY_BYTES]>,
+    kdf: KdfParams,
+    secrets: BTreeMap<String, StoredSecret>,
+}
+
+/// What the UI may see of a secret: never its value.
+#[derive(Clone, Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SecretInfo {
+    pub name: String,
+    pub inject: bool,
+}
+
+#[derive(Clone, Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct VaultStatus {
+    pub exists: bool,
+    pub unlocked: bool,
+}
+
+static PATH: Mutex<Option<PathBuf>> = Mutex::new(None);
+static VAULT: Mutex<Option<Unlocked>> = Mutex::new(None);
+
+/// Remembers where the vault lives and unlocks it with
+/// `ELIZA_VAULT_PASSPHRASE` if that is set and the vault exists. The
+/// variable is then removed from the environment, so no process the app
+/// starts inherits it; this runs at launch, before the app has started any
+/// threads.
+pub fn init(path: PathBuf) {
+    *PATH.lock().expect("PATH mutex should not be poisoned") = Some(path);
+    if let Ok(passphrase) = std::env::var(PASSPHRASE_ENV) {
+        let passphrase = Zeroizing::new(passphrase);
+        std::env::remove_var(PASSPHRASE_ENV);
+        if let Err(err) = unlock(&passphrase) {
+            eprintln!(
+                "Failed to unlock the secrets vault from {}: {}",
+                PASSPHRASE_ENV, err
+            );
+        }
+    }
+}
+
+fn path() -> Result<PathBuf, String> {
+    PATH.lock()
+        .expect("PATH mutex should not be poisoned")
+        .clone()
+        .ok_or_else(|| "The secrets vault is unavailable: no data directory".to_string())
+}
+
+fn status() -> VaultStatus {
+    VaultStatus {
+        exists: path().is_ok_and(|path| path.exists()),
+        unlocked: VAULT
+            .lock()
+            .expect("VAULT mutex should not be poisoned")
+            .is_some(),
+    }
+}
+
+/// Opens the existing vault. A vault is never created here, so a mistyped
+/// passphrase cannot become the vault's.
+pub fn unlock(passphrase: &str) -> Result<(), String> {
+    let path = path()?;
+    if !path.exists() {
+        return Err("There is no secrets vault yet; create one first".into());
+    }
+    let unlocked = open(&path, passphrase)?;
+    *VAULT.lock().expect("VAULT mutex should not be poisoned") = Some(unlocked);
+    Ok(())
+}
+
+/// Creates an empty vault protected by `passphrase` and unlocks it.
+/// `confirmation` is the passphrase typed a second time. An existing vault
+/// is never replaced.
+pub fn create(passphrase: &str, confirmation: &str) -> Result<(), String> {
+    if passphrase != confirmation {
+        return Err("The passphrases do not match".into());
+    }
+    if passphrase.chars().count() < MIN_PASSPHRASE_CHARS {
+        return Err(format!(
+            "The passphrase must be at least {} characters",
+            MIN_PASSPHRASE_CHARS
+        ));
+    }
+    let path = path()?;
+    // Held throughout, so two creates cannot both pass the check below.
+    let mut vault = VAULT.lock().expect("VAULT mutex should not be poisoned");
+    if path.exists() {
+        return Err(format!(
+            "A secrets vault already exists in {}",
+            path.display()
+        ));
+    }
+    let mut salt = [0u8; SALT_BYTES];
+    getrandom::fill(&mut salt).map_err(|err| err.to_string())?;
+    let kdf = KdfParams {
+        memory_kib: MIN_MEMORY_KIB,
+        iterations: MIN_ITERATIONS,
+        parallelism: 1,
+        salt: to_hex(&salt),
+    };
+    let unlocked = Unlocked {
+        key: derive_key(passphrase, &kdf)?,
+        kdf,
+        secrets: BTreeMap::new(),
+    };
+    save(&path, &unlocked)?;
+    println!("Created secrets vault in {}", path.display());
+    *vault = Some(unlocked);
+    Ok(())
+}
+
+fn open(path: &Path, passphrase: &str) -> Result<Unlocked, String> {
+    let contents =
+        fs::read(path).map_err(|err| format!("Failed to read {}: {}", path.display(), err))?;
+    let file: VaultFile = serde_json::from_slice(&contents)
+        .map_err(|err| format!("{} is not a secrets vault: {}", path.display(), err))?;
+    if file.version != FORMAT_VERSION {
+        return Err(format!(
+            "{} has vault format {}, this app reads {}",
+            path.display(),
+            file.version,
+            FORMAT_VERSION
+        ));
+    }
+    let key = derive_key(passphrase, &file.kdf)?;
+    let nonce = from_hex(&file.nonce)
+        .filter(|nonce| nonce.len() == NONCE_BYTES)
+        .ok_or_else(|| format!("{} has a malformed nonce", path.display()))?;
+    let ciphertext = from_hex(&file.ciphertext)
+        .ok_or_else(|| format!("{} has malformed contents", path.display()))?;
+    // A wrong passphrase and a tampered file look the same to the AEAD.
+    let plaintext = Zeroizing::new(
+        cipher(&key)
+            .decrypt(
+                XNonce::from_slice(&nonce),
+                Payload {
+                    msg: &ciphertext,
+                    aad: ASSOCIATED_DATA,
+                },
+            )
+            .map_err(|_| "Wrong passphrase, or the vault file is damaged".to_string())?,
+    );
+    let secrets = serde_json::from_slice(&plaintext)
+        .map_err(|err| format!("{} has unreadable contents: {}", path.display(), err))?;
+    Ok(Unlocked {
+        key,
+        kdf: file.kdf,
+        secrets,
+    })
+}
+
+/// Encrypts the secrets under a fresh nonce and replaces the file, so an
+/// interrupted write never leaves a half-written vault.
+fn save(path: &Path, unlocked: &Unlocked) -> Result<(), String> {
+    let plaintext =
+        Zeroizing::new(serde_json::to_vec(&unlocked.secrets).map_err(|err| err.to_string())?);
+    let mut nonce = [0u8; NONCE_BYTES];
+    getrandom::fill(&mut nonce).map_err(|err| err.to_string())?;
+    let ciphertext = cipher(&unlocked.key)
+        .encrypt(
+            XNonce::from_slice(&nonce),
+            Payload {
+                msg: &plaintext,
+                aad: ASSOCIATED_DATA,
+            },
+        )
+        .map_err(|_| "Failed to encrypt the secrets vault".to_string())?;
+    let file = VaultFile {
+        version: FORMAT_VERSION,
+        kdf: unlocked.kdf.clone(),
+        nonce: to_hex(&nonce),
+        ciphertext: to_hex(&ciphertext),
+    };
+    let contents = serde_json::to_vec_pretty(&file).map_err(|err| err.to_string())?;
+    let temp = path.with_extension("vault.tmp");
+    paths::write_private(&temp, &contents)
+        .and_then(|()| fs::rename(&temp, path))
+        .map_err(|err| format!("Failed to write {}: {}", path.display(), err))
+}
+
+fn derive_key(passphrase: &str, kdf: &KdfParams) -> Result<Zeroizing<[u8; KEY_BYTES]>, String> {
+    let in_bounds = (MIN_MEMORY_KIB..=MAX_MEMORY_KIB).contains(&kdf.memory_kib)
+        && (MIN_ITERATIONS..=MAX_ITERATIONS).contains(&kdf.iterations)
+        && (1..=MAX_PARALLELISM).contains(&kdf.parallelism);
+    if !in_bounds {
+        return Err(format!(
+            "The vault has out-of-range key parameters ({} KiB, {} iterations, {} lanes)",
+            kdf.memory_kib, kdf.iterations, kdf.parallelism
+        ));
+    }
+    let salt = from_hex(&kdf.salt)
+        .filter(|salt| salt.len() >= SALT_BYTES)
+        .ok_or_else(|| "The vault has a malformed salt".to_string())?;
+    let params = Params::new(
+        kdf.memory_kib,
+        kdf.iterations,
+        kdf.parallelism,
+        Some(KEY_BYTES),
+    )
+    .map_err(|err| format!("The vault has invalid key parameters: {}", err))?;
+    let mut key = Zeroizing::new([0u8; KEY_BYTES]);
+    Argon2::new(Algorithm::Argon2id, Version::V0x13, params)
+        .hash_password_into(passphrase.as_bytes(), &salt, key.as_mut())
+        .map_err(|err| format!("Failed to derive the vault key: {}", err))?;
+    Ok(key)
+}
+
+fn cipher(key: &[u8; KEY_BYTES]) -> XChaCha20Poly1305 {
+    XChaCha20Poly1305::new(key.into())
+}
+
+fn to_hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|byte| format!("{:02x}", byte)).collect()
+}
+
+fn from_hex(hex: &str) -> Option<Vec<u8>> {
+    if !hex.len().is_multiple_of(2) {
+        return None;
+    }
+    (0..hex.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(hex.get(i..i + 2)?, 16).ok())
+        .collect()
+}
+
+/// Secret names become environment variable names, so they follow the
+/// same rules.
+fn validate_name(name: &str) -> Result<(), String> {
+    let mut chars = name.chars();
+    let valid = chars
+        .next()
+        .is_some_and(|c| c.is_ascii_alphabetic() || c == '_')
+        && chars.all(|c| c.is_ascii_alphanumeric() || c == '_');
+    if !valid {
+        return Err(format!(
+            "{:?} is not a valid name; use letters, digits and underscores, e.g. OPENAI_API_KEY",
+            name
+        ));
+    }
+    if RESERVED_NAMES.contains(&name)
+        || RESERVED_PREFIXES
+            .iter()
+            .any(|prefix| name.starts_with(prefix))
+    {
+        return Err(format!("{} is set by the app and cannot be a secret", name));
+    }
+    Ok(())
+}
+
+/// Runs `change` on the unlocked secrets and saves them.
+fn modify(
+    change: impl FnOnce(&mut BTreeMap<String, StoredSecret>) -> Result<(), String>,
+) -> Result<Vec<SecretInfo>, String> {
+    let path = path()?;
+    let mut vault = VAULT.lock().expect("VAULT mutex should not be poisoned");
+    let unlocked = vault
+        .as_mut()
+        .ok_or_else(|| "The secrets vault is locked".to_string())?;
+    change(&mut unlocked.secrets)?;
+    save(&path, unlocked)?;
+    Ok(infos(unlocked))
+}
+
+fn infos(unlocked: &Unlocked) -> Vec<SecretInfo> {
+    unlocked
+        .secrets
+        .iter()
+        .map(|(name, secret)| SecretInfo {
+            name: name.clone(),
+            inject: secret.inject,
+        })
+        .collect()
+}
+
+/// The secrets marked for injection, as environment variables for the
+/// server. A locked vault contributes none, and a name that has since become
+/// reserved is skipped.
+pub fn server_env() -> Vec<(String, Zeroizing<String>)> {
+    let vault = VAULT.lock().expect("VAULT mutex should not be poisoned");
+    let Some(unlocked) = vault.as_ref() else {
+        if path().is_ok_and(|path| path.exists()) {
+            println!("Secrets vault is locked; its secrets are not passed to the server");
+        }
+        return Vec::new();
+    };
+    unlocked
+        .secrets
+        .iter()
+        .filter(|(name, secret)| secret.inject && validate_name(name).is_ok())
+        .map(|(name, secret)| (name.clone(), Zeroizing::new(secret.value.clone())))
+        .collect()
+}
+
+/// Whether the vault exists and is unlocked.
+#[tauri::command]
+pub fn get_vault_status() -> VaultStatus {
+    status()
+}
+
+/// Unlocks the existing vault. Key derivation is deliberately slow, so it
+/// runs off the main thread.
+#[tauri::command]
+pub async fn unlock_vault(passphrase: String) -> Result<VaultStatus, String> {
+    let passphrase = Zeroizing::new(passphrase);
+    tauri::async_runtime::spawn_blocking(move || unlock(&passphrase))
+        .await
+        .map_err(|err| err.to_string())??;
+    Ok(status())
+}
+
+/// Creates the vault with a passphrase given twice, and unlocks it.
+#[tauri::command]
+pub async fn create_vault(passphrase: String, confirmation: String) -> Result<VaultStatus, String> {
+    let passphrase = Zeroizing::new(passphrase);
+    let confirmation = Zeroizing::new(confirmation);
+    tauri::async_runtime::spawn_blocking(move || create(&passphrase, &confirmation))
+        .await
+        .map_err(|err| err.to_string())??;
+    Ok(status())
+}
+
+/// Forgets the key and the decrypted secrets until the next unlock.
+#[tauri::command]
+pub fn lock_vault() -> VaultStatus {
+    *VAULT.lock().expect("VAULT mutex should not be poisoned") = None;
+    status()
+}
+
+/// Names of the stored secrets and whether each is passed to the server.
+#[tauri::command]
+pub fn list_secrets() -> Result<Vec<SecretInfo>, String> {
+    VAULT
+        .lock()
+        .expect("VAULT mutex should not be poisoned")
+        .as_ref()
+        .map(infos)
+        .ok_or_else(|| "The secrets vault is locked".to_string())
+}
+
+/// Adds or replaces a secret. It reaches the server on its next start.
+#[tauri::command]
+pub fn set_secret(name: String, value: String, inject: bool) -> Result<Vec<SecretInfo>, String> {
+    validate_name(&name)?;
+    let secret = StoredSecret { value, inject };
+    modify(move |secrets| {
+        secrets.insert(name, secret);
+        Ok(())
+    })
+}
+
+/// Removes a secret.
+#[tauri::command]
+pub fn delete_secret(name: String) -> Result<Vec<SecretInfo>, String> {
+    modify(|secrets| {
+        secrets
+            .remove(&name)
+            .map(|_| ())
+            .ok_or_else(|| format!("No secret named {}", name))
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const PASSPHRASE: &str = "correct horse battery";
+
+    fn temp_dir(name: &str) -> PathBuf {
+        let dir =
+            std::env::temp_dir().join(format!("eliza-vault-test-{}-{}", name, std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    fn kdf() -> KdfParams {
+        KdfParams {
+            memory_kib: MIN_MEMORY_KIB,
+            iterations: MIN_ITERATIONS,
+            parallelism: 1,
+            salt: to_hex(&[7u8; SALT_BYTES]),
+        }
+    }
+
+    fn vault(secrets: &[(&str, &str)]) -> Unlocked {
+        let kdf = kdf();
+        Unlocked {
+            key: derive_key(PASSPHRASE, &kdf).unwrap(),
+            kdf,
+            secrets: secrets
+                .iter()
+                .map(|(name, value)| {
+                    let secret = StoredSecret {
+                        value: value.to_string(),
+                        inject: true,
+                    };
+                    (name.to_string(), secret)
+                })
+                .collect(),
+        }
+    }
+
+    fn read_file(path: &Path) -> VaultFile {
+        serde_json::from_slice(&fs::read(path).unwrap()).unwrap()
+    }
+
+    fn write_file(path: &Path, file: &VaultFile) {
+        fs::write(path, serde_json::to_vec(file).unwrap()).unwrap();
+    }
+
+    /// Flips one bit of a hex-encoded field.
+    fn tamper(hex: &str) -> String {
+        let mut bytes = from_hex(hex).unwrap();
+        bytes[0] ^= 1;
+        to_hex(&bytes)
+    }
+
+    #[test]
+    fn round_trips_with_the_right_passphrase() {
+        let dir = temp_dir("round-trip");
+        let path = dir.join(FILE_NAME);
+        save(&path, &vault(&[("OPENAI_API_KEY", "sk-test")])).unwrap();
+
+        let opened = open(&path, PASSPHRASE).unwrap();
+        assert_eq!(opened.secrets.len(), 1);
+        assert_eq!(opened.secrets["OPENAI_API_KEY"].value, "sk-test");
+        assert!(opened.secrets["OPENAI_API_KEY"].inject);
+        assert!(!String::from_utf8_lossy(&fs::read(&path).unwrap()).contains("sk-test"));
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn rejects_a_wrong_passphrase_and_a_tampered_file() {
+        let dir = temp_dir("tamper");
+        let path = dir.join(FILE_NAME);
+        save(&path, &vault(&[("OPENAI_API_KEY", "sk-test")])).unwrap();
+        let saved = read_file(&path);
+
+        assert!(open(&path, "not the passphrase").is_err());
+
+        let mut file = read_file(&path);
+        file.ciphertext = tamper(&saved.ciphertext);
+        write_file(&path, &file);
+        assert!(open(&path, PASSPHRASE).is_err());
+
+        let mut file = read_file(&path);
+        file.ciphertext = saved.ciphertext.clone();
+        file.nonce = tamper(&saved.nonce);
+        write_file(&path, &file);
+        assert!(open(&path, PASSPHRASE).is_err());
+
+        // Changing the salt changes the key.
+        let mut file = read_file(&path);
+        file.nonce = saved.nonce.clone();
+        file.kdf.salt = tamper(&saved.kdf.salt);
+        write_file(&path, &file);
+        assert!(open(&path, PASSPHRASE).is_err());
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn rejects_out_of_range_key_parameters() {
+        let weak = KdfParams {
+            memory_kib: 8,
+            ..kdf()
+        };
+        assert!(derive_key(PASSPHRASE, &weak).is_err());
+        let huge = KdfParams {
+            memory_kib: u32::MAX,
+            ..kdf()
+        };
+        assert!(derive_key(PASSPHRASE, &huge).is_err());
+        let slow = KdfParams {
+            iterations: u32::MAX,
+            ..kdf()
+        };
+        assert!(derive_key(PASSPHRASE, &slow).is_err());
+        let no_lanes = KdfParams {
+            parallelism: 0,
+            ..kdf()
+        };
+        assert!(derive_key(PASSPHRASE, &no_lanes).is_err());
+    }
+
+    #[test]
+    fn rejects_reserved_and_invalid_names() {
+        assert!(validate_name("OPENAI_API_KEY").is_ok());
+        assert!(validate_name("_PRIVATE").is_ok());
+        for name in [
+            "SERVER_PORT",
+            "SERVER_HOST",
+            "ELIZA_SERVER_AUTH_TOKEN",
+            "ELIZA_SERVER_ANYTHING",
+            "ELIZA_VAULT_PASSPHRASE",
+            "LOG_JSON_FORMAT",
+            "PATH",
+            "LD_PRELOAD",
+            "NODE_OPTIONS",
+            "DYLD_INSERT_LIBRARIES",
+        ] {
+            assert!(validate_name(name).is_err(), "{} should be reserved", name);
+        }
+        for name in ["", "1PASSWORD", "API-KEY", "API KEY", "KEY=VALUE", "ÉLIZA"] {
+            assert!(validate_name(name).is_err(), "{:?} should be invalid", name);
+        }
+    }
+
+    #[test]
+    fn keeps_the_old_file_when_a_save_fails() {
+        let dir = temp_dir("atomic");
+        let path = dir.join(FILE_NAME);
+        save(&path, &vault(&[("OPENAI_API_KEY", "old")])).unwrap();
+
+        // A directory where the temporary file goes makes the write fail.
+        fs::create_dir(path.with_extension("vault.tmp")).unwrap();
+        assert!(save(&path, &vault(&[("OPENAI_API_KEY", "new")])).is_err());
+
+        let opened = open(&path, PASSPHRASE).unwrap();
+        assert_eq!(opened.secrets["OPENAI_API_KEY"].value, "old");
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -23,7 +23,8 @@ describe('App Tests', () => {
     expect(content).toContain('useState');
     expect(content).toContain('createRoot');
     expect(content).toContain('ElizaWrapper');
-    expect(content).toContain('fetch');
+    expect(content).toContain('get_server_state');
+    expect(content).toContain('server-state');
   });
 
   test('React can be imported and used', () => {
@@ -49,6 +50,7 @@ describe('App Tests', () => {
     expect(packageJson.dependencies).toBeDefined();
     expect(packageJson.dependencies?.['react']).toBeDefined();
     expect(packageJson.dependencies?.['react-dom']).toBeDefined();
+    expect(packageJson.dependencies?.['@tauri-apps/api']).toBeDefined();
 
     expect(packageJson.devDependencies).toBeDefined();
     expect(packageJson.devDependencies?.['@tauri-apps/cli']).toBeDefined();
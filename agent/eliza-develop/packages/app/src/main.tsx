@@ -1,76 +1,290 @@
 import { StrictMode } from 'react';
 import ReactDOM from 'react-dom/client';
 import { useEffect, useState } from 'react';
+import { invoke } from '@tauri-apps/api/core';
+import { listen } from '@tauri-apps/api/event';
+
+/** Mirrors `ErrorReport` in src-tauri/src/error.rs. */
+type ErrorReport = {
+  kind: string;
+  message: string;
+  hint: string;
+};
+
+/** Mirrors `ServerState` in src-tauri/src/state.rs. */
+type ServerState =
+  | { state: 'idle' }
+  | { state: 'spawning'; attempt: number }
+  | { state: 'waitingForHealth'; pid: number | null }
+  | { state: 'ready'; version: string; agentsReady: boolean; external: boolean }
+  | { state: 'crashed'; error: ErrorReport; willRestart: boolean }
+  | { state: 'stopped' };
+
+/** Mirrors `PreflightReport` in src-tauri/src/preflight.rs. */
+type PreflightReport = {
+  passed: boolean;
+  checks: {
+    name: string;
+    status: 'pass' | 'warn' | 'fail' | 'skipped';
+    message: string;
+    hint: string | null;
+  }[];
+};
+
+const CHECK_COLORS = { pass: 'green', warn: '#b8860b', fail: 'red', skipped: '#888' };
+
+/** Lists what the preflight checks found, so a failure shows its cause. */
+function PreflightChecks() {
+  const [report, setReport] = useState<PreflightReport | null>(null);
+
+  useEffect(() => {
+    invoke<PreflightReport>('run_preflight')
+      .then(setReport)
+      .catch((err: unknown) => console.error('Failed to run preflight checks:', err));
+  }, []);
+
+  if (!report) {
+    return <p style={{ color: '#555' }}>Running checks...</p>;
+  }
+  return (
+    <ul style={{ listStyle: 'none', padding: 0, textAlign: 'left' }}>
+      {report.checks.map((check) => (
+        <li key={check.name} style={{ margin: '4px 0' }}>
+          <strong style={{ color: CHECK_COLORS[check.status] }}>{check.status}</strong>{' '}
+          {check.message}
+          {check.hint && check.status !== 'pass' && (
+            <div style={{ color: '#555', fontSize: '14px' }}>{check.hint}</div>
+          )}
+        </li>
+      ))}
+    </ul>
+  );
+}
+
+/** Mirrors `ServerRecord` in src-tauri/src/pid_file.rs. */
+type OrphanServer = {
+  pid: number;
+  startedAt: number;
+  command: string;
+  port: number;
+  appPid: number;
+  bootId: string | null;
+};
+
+/** Offers to stop a server an earlier app session left running. */
+function OrphanBanner({
+  orphan,
+  onStop,
+  onDismiss,
+}: {
+  orphan: OrphanServer;
+  onStop: () => void;
+  onDismiss: () => void;
+}) {
+  return (
+    <div
+      style={{
+        display: 'flex',
+        alignItems: 'center',
+        gap: '12px',
+        padding: '8px 16px',
+        backgroundColor: '#fff4ce',
+        fontFamily: 'sans-serif',
+        fontSize: '14px',
+      }}
+    >
+      <span style={{ flex: 1 }}>
+        An Eliza server from an earlier session is still running (pid {orphan.pid}, port{' '}
+        {orphan.port}).
+      </span>
+      <button type="button" onClick={onStop}>
+        Stop it
+      </button>
+      <button type="button" onClick={onDismiss}>
+        Keep it
+      </button>
+    </div>
+  );
+}
+
+/** Mirrors `SettingsView` in src-tauri/src/settings.rs. */
+type SettingsView = {
+  settings: { autoStart: boolean } & Record<string, unknown>;
+  path: string | null;
+  problems: { key: string; message: string }[];
+  restartRequired: boolean;
+};
 
 function ElizaWrapper() {
-  const [status, setStatus] = useState<'starting' | 'running' | 'error'>('starting');
+  const [serverState, setServerState] = useState<ServerState>({ state: 'idle' });
   const [error, setError] = useState<string | null>(null);
   const [retryCount, setRetryCount] = useState(0);
-  const [isServerAccessible, setIsServerAccessible] = useState(false);
-
-  const checkServerAccessibility = async (): Promise<boolean> => {
-    const response = await fetch('http://localhost:3000', {
-      method: 'HEAD',
-      mode: 'no-cors',
-    });
-    return response.type === 'opaque' || response.status === 0;
-  };
+  const [orphan, setOrphan] = useState<OrphanServer | null>(null);
+  const [clientPath, setClientPath] = useState('');
+  const [settings, setSettings] = useState<SettingsView | null>(null);
 
   useEffect(() => {
-    let checkInterval: ReturnType<typeof setInterval> | undefined;
-    let timeoutId: ReturnType<typeof setTimeout> | undefined;
-
-    const startServer = async () => {
-      setStatus('running');
-
-      checkInterval = setInterval(async () => {
-        const isAccessible = await checkServerAccessibility();
-        if (isAccessible) {
-          setIsServerAccessible(true);
-          clearInterval(checkInterval);
-          clearTimeout(timeoutId);
-        }
-      }, 1000);
+    invoke<SettingsView>('get_settings')
+      .then(setSettings)
+      .catch((err: unknown) => console.error('Failed to read settings:', err));
+  }, []);
 
-      timeoutId = setTimeout(() => {
-        clearInterval(checkInterval);
-      }, 60000);
+  useEffect(() => {
+    // Sent by `app open <agentId>` from the command line.
+    const unlisten = listen<string>('open-agent', (event) => {
+      setClientPath(`/chat/${encodeURIComponent(event.payload)}`);
+    });
+    return () => {
+      void unlisten.then((fn) => fn());
     };
+  }, []);
 
-    startServer().catch((err: Error) => {
-      console.error('Failed to start Eliza server:', err);
-      setStatus('error');
-      setError(`Failed to start Eliza server: ${err.message}`);
+  useEffect(() => {
+    let cancelled = false;
+    const unlisten = listen<ServerState>('server-state', (event) => {
+      setServerState(event.payload);
     });
 
+    invoke<ServerState>('get_server_state')
+      .then((state) => {
+        if (!cancelled) {
+          setServerState(state);
+        }
+      })
+      .catch((err: unknown) => {
+        console.error('Failed to read Eliza server state:', err);
+        setError(`Failed to read Eliza server state: ${String(err)}`);
+      });
+
+    invoke<OrphanServer | null>('get_orphan_server')
+      .then((found) => {
+        if (!cancelled) {
+          setOrphan(found);
+        }
+      })
+      .catch((err: unknown) => console.error('Failed to check for an old Eliza server:', err));
+
     return () => {
-      clearInterval(checkInterval);
-      clearTimeout(timeoutId);
+      cancelled = true;
+      void unlisten.then((fn) => fn());
     };
   }, [retryCount]);
 
   const handleRetry = () => {
-    setStatus('starting');
     setError(null);
-    setRetryCount((prev) => prev + 1);
+    invoke<ServerState>('retry_startup')
+      .then(setServerState)
+      .catch((err: unknown) => {
+        console.error('Failed to restart Eliza server:', err);
+        setError(`Failed to restart Eliza server: ${String(err)}`);
+      })
+      .finally(() => setRetryCount((prev) => prev + 1));
   };
 
-  if (status === 'running' && isServerAccessible) {
-    return (
-      <div style={{ width: '100%', height: '100vh', margin: 0, padding: 0 }}>
-        <iframe
-          src="http://localhost:3000"
-          title="Eliza Client"
-          style={{
-            width: '100%',
-            height: '100%',
-            border: 'none',
-          }}
+  const [exportedTo, setExportedTo] = useState<string | null>(null);
+
+  const handleExportDiagnostics = () => {
+    invoke<string>('export_diagnostics')
+      .then(setExportedTo)
+      .catch((err: unknown) => {
+        console.error('Failed to export diagnostics:', err);
+        setExportedTo(`Failed to export diagnostics: ${String(err)}`);
+      });
+  };
+
+  const handleStopOrphan = () => {
+    setOrphan(null);
+    invoke<ServerState>('reap_orphan_server')
+      .then(setServerState)
+      .catch((err: unknown) => {
+        console.error('Failed to stop the old Eliza server:', err);
+        setError(String(err));
+      });
+  };
+
+  const handleStart = () => {
+    invoke<ServerState>('start_server')
+      .then(setServerState)
+      .catch((err: unknown) => {
+        console.error('Failed to start Eliza server:', err);
+        setError(`Failed to start Eliza server: ${String(err)}`);
+      });
+  };
+
+  const settingsProblems = settings && settings.problems.length > 0 && (
+    <div
+      style={{
+        padding: '8px 16px',
+        backgroundColor: '#fde7e9',
+        fontFamily: 'sans-serif',
+        fontSize: '14px',
+      }}
+    >
+      Using default settings; {settings.path ?? 'the settings file'} has problems:{' '}
+      {settings.problems.map((problem) => `${problem.key} ${problem.message}`).join('; ')}
+    </div>
+  );
+
+  const banner = (
+    <>
+      {settingsProblems}
+      {orphan && (
+        <OrphanBanner
+          orphan={orphan}
+          onStop={handleStopOrphan}
+          onDismiss={() => setOrphan(null)}
         />
+      )}
+    </>
+  );
+
+  const showClient = serverState.state === 'ready' && !error;
+
+  useEffect(() => {
+    // An orphan banner waits for an answer before the client replaces this page.
+    if (!showClient || orphan) {
+      return;
+    }
+    // The client is served by the app's proxy, which forwards to wherever
+    // the server listens and adds the API key itself. Each link opens it
+    // once.
+    invoke<string>('get_client_url', { path: clientPath || null })
+      .then((url) => window.location.replace(url))
+      .catch((err: unknown) => {
+        console.error('Failed to open the Eliza client:', err);
+        setError(`Failed to open the Eliza client: ${String(err)}`);
+      });
+  }, [showClient, orphan, clientPath]);
+
+  if (showClient) {
+    return (
+      <div style={{ height: '100vh', fontFamily: 'sans-serif', textAlign: 'center' }}>
+        {banner}
+        <p>Opening Eliza...</p>
       </div>
     );
   }
 
+  let failure: { message: string; hint?: string } | null = null;
+  if (error) {
+    failure = { message: error };
+  } else if (serverState.state === 'crashed' && !serverState.willRestart) {
+    failure = serverState.error;
+  } else if (serverState.state === 'stopped') {
+    failure = { message: 'The Eliza server has stopped.' };
+  }
+
+  let progress = 'Please wait while we start the backend services.';
+  if (serverState.state === 'waitingForHealth') {
+    progress = 'Waiting for the server to become healthy...';
+  } else if (serverState.state === 'crashed') {
+    // A remote server is never restarted by the app, only checked again.
+    const next = serverState.error.kind === 'remoteUnavailable' ? 'Reconnecting' : 'Restarting';
+    progress = `${serverState.error.message}. ${next}...`;
+  } else if (serverState.state === 'spawning' && serverState.attempt > 0) {
+    progress = `Restarting the backend services (attempt ${serverState.attempt})...`;
+  }
+
   return (
     <div
       style={{
@@ -84,10 +298,21 @@ function ElizaWrapper() {
         fontFamily: 'sans-serif',
       }}
     >
-      {status === 'error' ? (
+      {banner}
+      {serverState.state === 'idle' && settings && !settings.settings.autoStart && !failure ? (
+        <>
+          <h2>Eliza Server</h2>
+          <p>The server does not start automatically.</p>
+          <button type="button" onClick={handleStart}>
+            Start server
+          </button>
+        </>
+      ) : failure ? (
         <>
           <h2 style={{ color: 'red' }}>Error</h2>
-          <p>{error}</p>
+          <p>{failure.message}</p>
+          {failure.hint && <p style={{ color: '#555' }}>{failure.hint}</p>}
+          <PreflightChecks key={retryCount} />
           <button
             type="button"
             onClick={handleRetry}
@@ -104,11 +329,26 @@ function ElizaWrapper() {
           >
             Retry
           </button>
+          <button
+            type="button"
+            onClick={handleExportDiagnostics}
+            style={{
+              marginTop: '12px',
+              padding: '6px 12px',
+              background: 'none',
+              border: '1px solid #ccc',
+              borderRadius: '4px',
+              cursor: 'pointer',
+            }}
+          >
+            Export diagnostics
+          </button>
+          {exportedTo && <p style={{ color: '#555', fontSize: '14px' }}>{exportedTo}</p>}
         </>
       ) : (
         <>
           <h2>Starting Eliza Server...</h2>
-          <p>Please wait while we start the backend services.</p>
+          <p>{progress}</p>
           <div
             style={{
               marginTop: '20px',